@@ -2,7 +2,7 @@ use serde_json::json;
 use std::error::Error;
 use std::fmt;
 
-use kroeg_tap::{as2, assign_id, Context, MessageHandler, StoreItem};
+use kroeg_tap::{as2, assign_id, Context, ErrorKind, MessageHandler, StoreItem, TapError};
 
 pub struct AutomaticCreateHandler;
 
@@ -23,6 +23,12 @@ impl fmt::Display for AutomaticCreateError {
 
 impl Error for AutomaticCreateError {}
 
+impl From<AutomaticCreateError> for TapError {
+    fn from(error: AutomaticCreateError) -> TapError {
+        TapError::new(ErrorKind::InvalidInput, error)
+    }
+}
+
 const DEFAULT_ACTIVITIES: &'static [&'static str] = &[
     as2!(Accept),
     as2!(Add),
@@ -84,7 +90,7 @@ impl MessageHandler for AutomaticCreateHandler {
         context: &mut Context<'_, '_>,
         _inbox: &mut String,
         elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    ) -> Result<(), TapError> {
         let item = match context.entity_store.get(elem.to_owned(), false).await? {
             Some(item) => item,
             None => return Ok(()),
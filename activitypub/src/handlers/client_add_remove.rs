@@ -0,0 +1,348 @@
+use jsonld::nodemap::Pointer;
+use std::error::Error;
+use std::fmt;
+
+use kroeg_tap::{as2, kroeg, ActivityHandler, Context, ErrorKind, StoreItem, TapError};
+
+use super::create_actor::{FEATURED, HOME_TIMELINE};
+
+#[derive(Debug)]
+pub enum ClientAddRemoveError {
+    MissingRequired(String),
+    MissingTarget,
+    MissingObject,
+    NotOwner,
+    NotAttributed,
+    ManagedCollection,
+}
+
+impl fmt::Display for ClientAddRemoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientAddRemoveError::MissingRequired(ref val) => write!(
+                f,
+                "The {} predicate is missing or occurs more than once",
+                val
+            ),
+            ClientAddRemoveError::MissingTarget => write!(f, "The target collection is missing!"),
+            ClientAddRemoveError::MissingObject => write!(f, "The object to be added is missing!"),
+            ClientAddRemoveError::NotOwner => {
+                write!(f, "The target collection is not owned by the actor")
+            }
+            ClientAddRemoveError::NotAttributed => write!(
+                f,
+                "Only objects attributed to the actor can be added to this collection"
+            ),
+            ClientAddRemoveError::ManagedCollection => {
+                write!(f, "The target collection is managed by the server")
+            }
+        }
+    }
+}
+
+impl Error for ClientAddRemoveError {}
+
+impl From<ClientAddRemoveError> for TapError {
+    fn from(error: ClientAddRemoveError) -> TapError {
+        let kind = match error {
+            ClientAddRemoveError::MissingRequired(_) => ErrorKind::InvalidInput,
+            ClientAddRemoveError::MissingTarget => ErrorKind::NotFound,
+            ClientAddRemoveError::MissingObject => ErrorKind::NotFound,
+            ClientAddRemoveError::NotOwner => ErrorKind::Unauthorized,
+            ClientAddRemoveError::NotAttributed => ErrorKind::Unauthorized,
+            ClientAddRemoveError::ManagedCollection => ErrorKind::Unauthorized,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
+pub struct ClientAddRemoveHandler;
+
+#[async_trait::async_trait]
+impl ActivityHandler for ClientAddRemoveHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Add), as2!(Remove)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        _inbox: &str,
+        elem: &StoreItem,
+    ) -> Result<(), TapError> {
+        let is_add = elem.main().types.iter().any(|f| f == as2!(Add));
+
+        let target = if let [Pointer::Id(id)] = &elem.main()[as2!(target)] as &[Pointer] {
+            id.clone()
+        } else {
+            return Err(ClientAddRemoveError::MissingRequired(as2!(target).to_owned()).into());
+        };
+
+        let target = context
+            .entity_store
+            .get(target, true)
+            .await?
+            .ok_or(ClientAddRemoveError::MissingTarget)?;
+
+        // Only collections on this server that are part of the actor themselves can be managed.
+        let subject = Pointer::Id(context.user.subject.to_owned());
+        if !target.is_owned(context) || &target.main()[as2!(partOf)] != &[subject.clone()] {
+            return Err(ClientAddRemoveError::NotOwner.into());
+        }
+
+        // Boxes, and the collections the server keeps for the actor, are only changed
+        //  by the handlers maintaining them.
+        if target
+            .sub(kroeg!(meta))
+            .map(|meta| !meta[kroeg!(box)].is_empty())
+            .unwrap_or(false)
+        {
+            return Err(ClientAddRemoveError::ManagedCollection.into());
+        }
+
+        let target_id = Pointer::Id(target.id().to_owned());
+        let is_featured = match context
+            .entity_store
+            .get(context.user.subject.to_owned(), false)
+            .await?
+        {
+            Some(actor) => {
+                for predicate in &[as2!(followers), as2!(following), as2!(liked), HOME_TIMELINE] {
+                    if actor.main()[predicate].contains(&target_id) {
+                        return Err(ClientAddRemoveError::ManagedCollection.into());
+                    }
+                }
+
+                actor.main()[FEATURED] == [target_id]
+            }
+            None => false,
+        };
+
+        let mut objects = Vec::new();
+        for object in &elem.main()[as2!(object)] {
+            let id = match object {
+                Pointer::Id(id) => id,
+                _ => {
+                    return Err(
+                        ClientAddRemoveError::MissingRequired(as2!(object).to_owned()).into(),
+                    )
+                }
+            };
+
+            // Pinning is only allowed for the actor's own objects.
+            if is_add && is_featured {
+                let object = context
+                    .entity_store
+                    .get(id.to_owned(), false)
+                    .await?
+                    .ok_or(ClientAddRemoveError::MissingObject)?;
+
+                if !object.is_owned(context)
+                    || !object.main()[as2!(attributedTo)].contains(&subject)
+                {
+                    return Err(ClientAddRemoveError::NotAttributed.into());
+                }
+            }
+
+            objects.push(id.to_owned());
+        }
+
+        if objects.is_empty() {
+            return Err(ClientAddRemoveError::MissingRequired(as2!(object).to_owned()).into());
+        }
+
+        for object in objects {
+            if is_add {
+                context
+                    .entity_store
+                    .insert_collection(target.id().to_owned(), object)
+                    .await?;
+            } else {
+                context
+                    .entity_store
+                    .remove_collection(target.id().to_owned(), object)
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ClientAddRemoveError, ClientAddRemoveHandler};
+    use crate::handlers::FEATURED;
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler};
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![
+                object_under_test!(local "/subject" => {
+                    types => [as2!(Person)];
+                    FEATURED => ["/subject/featured"];
+                    as2!(followers) => ["/subject/followers"];
+                }),
+                object_under_test!(local "/subject/followers" => {
+                    types => [as2!(OrderedCollection)];
+                    as2!(partOf) => ["/subject"];
+                }),
+                object_under_test!(local "/subject/featured" => {
+                    types => [as2!(OrderedCollection)];
+                    as2!(partOf) => ["/subject"];
+                }),
+                object_under_test!(local "/other/featured" => {
+                    types => [as2!(OrderedCollection)];
+                    as2!(partOf) => ["/other"];
+                }),
+                object_under_test!(local "/own" => {
+                    types => [as2!(Note)];
+                    as2!(attributedTo) => ["/subject"];
+                }),
+                object_under_test!(remote "/foreign" => {
+                    types => [as2!(Note)];
+                    as2!(attributedTo) => ["/other"];
+                }),
+                object_under_test!(remote "/claimed" => {
+                    types => [as2!(Note)];
+                    as2!(attributedTo) => ["/subject"];
+                }),
+                object_under_test!(local "/add/claimed" => {
+                    types => [as2!(Add)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/claimed"];
+                    as2!(target) => ["/subject/featured"];
+                }),
+                object_under_test!(local "/add/followers" => {
+                    types => [as2!(Add)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/other"];
+                    as2!(target) => ["/subject/followers"];
+                }),
+                object_under_test!(local "/remove/own" => {
+                    types => [as2!(Remove)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/own"];
+                    as2!(target) => ["/subject/featured"];
+                }),
+                object_under_test!(local "/add/own" => {
+                    types => [as2!(Add)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/own"];
+                    as2!(target) => ["/subject/featured"];
+                }),
+                object_under_test!(local "/add/foreign" => {
+                    types => [as2!(Add)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/foreign"];
+                    as2!(target) => ["/subject/featured"];
+                }),
+                object_under_test!(local "/add/other" => {
+                    types => [as2!(Add)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/own"];
+                    as2!(target) => ["/other/featured"];
+                }),
+            ]),
+            (),
+        )
+    }
+
+    #[test]
+    fn pins_own_object() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ClientAddRemoveHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/add/own".to_owned(),
+        )) {
+            Ok(()) => {
+                assert!(
+                    store.has_read_all(&["/add/own", "/subject/featured", "/own"]),
+                    "Handler did not read all the expected objects"
+                );
+                assert!(
+                    store.contains("/subject/featured", "/own"),
+                    "Handler did not add the object"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    fn refuse(id: &str) -> ClientAddRemoveError {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ClientAddRemoveHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut id.to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted {}", id),
+            Err(e) => match e.downcast() {
+                Ok(val) => *val,
+                Err(e) => panic!("handler refused {}: {}", id, e),
+            },
+        }
+    }
+
+    #[test]
+    fn refuses_foreign_object() {
+        match refuse("/add/foreign") {
+            ClientAddRemoveError::NotAttributed => { /* ok! */ }
+            e => panic!("handler refused object for wrong reason: {}", e),
+        }
+    }
+
+    #[test]
+    fn refuses_remote_object_claiming_actor() {
+        match refuse("/add/claimed") {
+            ClientAddRemoveError::NotAttributed => { /* ok! */ }
+            e => panic!("handler refused object for wrong reason: {}", e),
+        }
+    }
+
+    #[test]
+    fn refuses_unowned_collection() {
+        match refuse("/add/other") {
+            ClientAddRemoveError::NotOwner => { /* ok! */ }
+            e => panic!("handler refused object for wrong reason: {}", e),
+        }
+    }
+
+    #[test]
+    fn refuses_managed_collection() {
+        match refuse("/add/followers") {
+            ClientAddRemoveError::ManagedCollection => { /* ok! */ }
+            e => panic!("handler refused object for wrong reason: {}", e),
+        }
+    }
+
+    #[test]
+    fn unpins_object() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        for id in &["/add/own", "/remove/own"] {
+            if let Err(e) = block_on(ClientAddRemoveHandler.handle(
+                &mut context,
+                &mut "/outbox".to_owned(),
+                &mut id.to_string(),
+            )) {
+                panic!("handler returned error: {}", e);
+            }
+        }
+
+        assert!(
+            !store.contains("/subject/featured", "/own"),
+            "Handler did not remove the object"
+        );
+    }
+}
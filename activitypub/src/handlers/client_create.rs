@@ -3,12 +3,13 @@ use serde_json::json;
 use std::error::Error;
 use std::fmt;
 
-use kroeg_tap::{as2, assign_id, Context, MessageHandler, StoreItem};
+use kroeg_tap::{as2, assign_id, ActivityHandler, Context, ErrorKind, StoreItem, TapError};
 
 #[derive(Debug)]
 pub enum ClientCreateError {
     ExistingPredicate(String),
     MissingRequired(String),
+    MissingObject,
 }
 
 impl fmt::Display for ClientCreateError {
@@ -22,32 +23,41 @@ impl fmt::Display for ClientCreateError {
             ClientCreateError::ExistingPredicate(ref val) => {
                 write!(f, "The {} predicate should not have been passed", val)
             }
+            ClientCreateError::MissingObject => {
+                write!(f, "The object being created could not be found")
+            }
         }
     }
 }
 
 impl Error for ClientCreateError {}
 
+impl From<ClientCreateError> for TapError {
+    fn from(error: ClientCreateError) -> TapError {
+        let kind = match error {
+            ClientCreateError::ExistingPredicate(_) => ErrorKind::InvalidInput,
+            ClientCreateError::MissingRequired(_) => ErrorKind::InvalidInput,
+            ClientCreateError::MissingObject => ErrorKind::NotFound,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
 pub struct ClientCreateHandler;
 
 #[async_trait::async_trait]
-impl MessageHandler for ClientCreateHandler {
-    async fn handle(
+impl ActivityHandler for ClientCreateHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create)]
+    }
+
+    async fn handle_activity(
         &self,
         context: &mut Context<'_, '_>,
-        _inbox: &mut String,
-        elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        let elem = context
-            .entity_store
-            .get(elem.to_owned(), false)
-            .await?
-            .expect("Cannot find the entity being handled on");
-
-        if !elem.main().types.contains(&as2!(Create).to_owned()) {
-            return Ok(());
-        }
-
+        _inbox: &str,
+        elem: &StoreItem,
+    ) -> Result<(), TapError> {
         let elem = if let [Pointer::Id(id)] = &elem.main()[as2!(object)] as &[Pointer] {
             id.clone()
         } else {
@@ -58,7 +68,7 @@ impl MessageHandler for ClientCreateHandler {
             .entity_store
             .get(elem, false)
             .await?
-            .expect("Object pointed at doesn't exist?!");
+            .ok_or(ClientCreateError::MissingObject)?;
 
         for &itemname in &["likes", "shares", "replies"] {
             let id = assign_id(
@@ -98,3 +108,113 @@ impl MessageHandler for ClientCreateHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ClientCreateError, ClientCreateHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use jsonld::nodemap::Pointer;
+    use kroeg_tap::{
+        as2, ErrorKind, GeneratedIds, IdGenerator, IdLayout, MessageHandler, StoreItem,
+    };
+    use std::sync::Arc;
+
+    fn objects() -> Vec<StoreItem> {
+        vec![
+            object_under_test!(local "/create" => {
+                types => [as2!(Create)];
+                as2!(object) => ["/object"];
+            }),
+            object_under_test!(local "/create/missing" => {
+                types => [as2!(Create)];
+                as2!(object) => ["/missing"];
+            }),
+            object_under_test!(local "/object" => {
+                types => [as2!(Note)];
+            }),
+        ]
+    }
+
+    #[test]
+    fn ignores_missing_activity() {
+        let mut store = TestStore::new(objects());
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        if let Err(e) = block_on(ClientCreateHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/missing".to_owned(),
+        )) {
+            panic!("handler returned error: {}", e);
+        }
+
+        assert!(store.item("/object").unwrap().main()[as2!(likes)].is_empty());
+    }
+
+    #[test]
+    fn refuses_missing_object() {
+        let mut store = TestStore::new(objects());
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ClientCreateHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/create/missing".to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted a Create of a missing object"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    ClientCreateError::MissingObject => { /* ok! */ }
+                    e => panic!("handler refused activity for wrong reason: {}", e),
+                },
+
+                Err(e) => panic!("handler refused activity: {}", e),
+            },
+        }
+    }
+
+    #[test]
+    fn fails_when_ids_are_exhausted() {
+        let mut store = TestStore::occupied(objects());
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ClientCreateHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/create".to_owned(),
+        )) {
+            Ok(()) => panic!("handler assigned an ID that is already in use"),
+            Err(e) => assert_eq!(e.kind(), ErrorKind::Conflict),
+        }
+    }
+
+    #[test]
+    fn uses_id_strategy() {
+        let mut store = TestStore::new(objects());
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+        context.id_strategy = Arc::new(GeneratedIds::new(IdGenerator::Uuid, IdLayout::Flat));
+
+        if let Err(e) = block_on(ClientCreateHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/create".to_owned(),
+        )) {
+            panic!("handler returned error: {}", e);
+        }
+
+        match &store.item("/object").unwrap().main()[as2!(likes)] as &[Pointer] {
+            [Pointer::Id(likes)] => assert!(
+                likes.starts_with("/objects/") && likes.len() == "/objects/".len() + 36,
+                "Handler did not use the ID strategy of the context: {}",
+                likes
+            ),
+            _ => panic!("Handler did not create a likes collection"),
+        }
+    }
+}
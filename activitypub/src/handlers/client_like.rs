@@ -1,27 +1,21 @@
 use jsonld::nodemap::Pointer;
-use std::error::Error;
 
-use kroeg_tap::{as2, Context, MessageHandler};
+use kroeg_tap::{as2, ActivityHandler, Context, StoreItem, TapError};
 
 pub struct ClientLikeHandler;
 
 #[async_trait::async_trait]
-impl MessageHandler for ClientLikeHandler {
-    async fn handle(
+impl ActivityHandler for ClientLikeHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Like)]
+    }
+
+    async fn handle_activity(
         &self,
         context: &mut Context<'_, '_>,
-        _inbox: &mut String,
-        elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        let elem = match context.entity_store.get(elem.to_owned(), false).await? {
-            Some(elem) => elem,
-            None => return Ok(()),
-        };
-
-        if !elem.main().types.iter().any(|f| f == as2!(Like)) {
-            return Ok(());
-        }
-
+        _inbox: &str,
+        elem: &StoreItem,
+    ) -> Result<(), TapError> {
         let subject = match context
             .entity_store
             .get(context.user.subject.clone(), false)
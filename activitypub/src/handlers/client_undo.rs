@@ -2,7 +2,7 @@ use jsonld::nodemap::Pointer;
 use std::error::Error;
 use std::fmt;
 
-use kroeg_tap::{as2, Context, MessageHandler};
+use kroeg_tap::{as2, ActivityHandler, Context, ErrorKind, StoreItem, TapError};
 
 #[derive(Debug)]
 pub enum ClientUndoError {
@@ -29,6 +29,18 @@ impl fmt::Display for ClientUndoError {
 
 impl Error for ClientUndoError {}
 
+impl From<ClientUndoError> for TapError {
+    fn from(error: ClientUndoError) -> TapError {
+        let kind = match error {
+            ClientUndoError::DifferingActor => ErrorKind::Unauthorized,
+            ClientUndoError::MissingRequired(_) => ErrorKind::InvalidInput,
+            ClientUndoError::MissingUndone => ErrorKind::NotFound,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
 fn equals_any_order(a: &Vec<Pointer>, b: &Vec<Pointer>) -> bool {
     if a.len() != b.len() {
         return false;
@@ -46,23 +58,17 @@ fn equals_any_order(a: &Vec<Pointer>, b: &Vec<Pointer>) -> bool {
 pub struct ClientUndoHandler;
 
 #[async_trait::async_trait]
-impl MessageHandler for ClientUndoHandler {
-    async fn handle(
+impl ActivityHandler for ClientUndoHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Undo)]
+    }
+
+    async fn handle_activity(
         &self,
         context: &mut Context<'_, '_>,
-        _inbox: &mut String,
-        elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        let elem = context
-            .entity_store
-            .get(elem.to_owned(), false)
-            .await?
-            .expect("Cannot find the entity being operated on?!");
-
-        if !elem.main().types.iter().any(|f| f == &as2!(Undo)) {
-            return Ok(());
-        }
-
+        _inbox: &str,
+        elem: &StoreItem,
+    ) -> Result<(), TapError> {
         let undone = if let [Pointer::Id(id)] = &elem.main()[as2!(object)] as &[Pointer] {
             id.clone()
         } else {
@@ -155,3 +161,54 @@ impl MessageHandler for ClientUndoHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ClientUndoError, ClientUndoHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler};
+
+    #[test]
+    fn ignores_missing_activity() {
+        let mut store = TestStore::new(vec![]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        if let Err(e) = block_on(ClientUndoHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/missing".to_owned(),
+        )) {
+            panic!("handler returned error: {}", e);
+        }
+    }
+
+    #[test]
+    fn refuses_missing_undone() {
+        let mut store = TestStore::new(vec![object_under_test!(local "/undo" => {
+            types => [as2!(Undo)];
+            as2!(actor) => ["/subject"];
+            as2!(object) => ["/missing"];
+        })]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ClientUndoHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/undo".to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted an Undo of a missing object"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    ClientUndoError::MissingUndone => { /* ok! */ }
+                    e => panic!("handler refused activity for wrong reason: {}", e),
+                },
+
+                Err(e) => panic!("handler refused activity: {}", e),
+            },
+        }
+    }
+}
@@ -0,0 +1,354 @@
+use jsonld::nodemap::{Entity, Pointer, Value};
+use openssl::sha::sha256;
+use serde_json::Value as JValue;
+
+use kroeg_tap::{
+    as2, assign_id, kroeg, ostatus, ActivityHandler, Context, StoreError, StoreItem, TapError,
+};
+
+use super::create_actor::build_collection;
+
+/// The conversation predicate as used by Mastodon and other OStatus descendants.
+pub const OSTATUS_CONVERSATION: &'static str = ostatus!(conversation);
+
+/// The type of the collections created to track conversations.
+pub const CONVERSATION: &'static str = kroeg!(Conversation);
+
+/// The conversation an entity explicitly claims to be part of, if any.
+fn declared_conversation(entity: &Entity) -> Option<String> {
+    for predicate in &[as2!(context), OSTATUS_CONVERSATION] {
+        match entity[predicate].first() {
+            Some(Pointer::Id(id)) => return Some(id.to_owned()),
+            Some(Pointer::Value(Value {
+                value: JValue::String(id),
+                ..
+            })) => return Some(id.to_owned()),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// The local collection an object has been assigned to before.
+fn assigned_conversation(item: &mut StoreItem) -> Option<String> {
+    match item.meta()[kroeg!(conversation)].first() {
+        Some(Pointer::Id(id)) => Some(id.to_owned()),
+        _ => None,
+    }
+}
+
+/// The ID of the local collection tracking a conversation from elsewhere.
+fn conversation_collection(context: &Context, conversation: &str) -> String {
+    let hash = sha256(conversation.as_bytes());
+    let hash: String = hash[..16].iter().map(|f| format!("{:02x}", f)).collect();
+
+    format!("{}/conversation/{}", context.server_base, hash)
+}
+
+/// Finds the local collection of the conversation an object is part of, either
+///  declared on the object itself, or inherited from the object it replies to.
+async fn find_conversation(
+    context: &mut Context<'_, '_>,
+    object: &mut StoreItem,
+) -> Result<Option<(String, String)>, StoreError> {
+    if let Some(collection) = assigned_conversation(object) {
+        let conversation =
+            declared_conversation(object.main()).unwrap_or_else(|| collection.to_owned());
+        return Ok(Some((conversation, collection)));
+    }
+
+    let declared = match declared_conversation(object.main()) {
+        Some(declared) => Some(declared),
+        None => match &object.main()[as2!(inReplyTo)] as &[Pointer] {
+            [Pointer::Id(parent)] => {
+                match context.entity_store.get(parent.to_owned(), false).await? {
+                    Some(mut parent) => {
+                        if let Some(collection) = assigned_conversation(&mut parent) {
+                            let conversation = declared_conversation(parent.main())
+                                .unwrap_or_else(|| collection.to_owned());
+                            return Ok(Some((conversation, collection)));
+                        }
+
+                        declared_conversation(parent.main())
+                    }
+
+                    None => None,
+                }
+            }
+
+            _ => None,
+        },
+    };
+
+    let declared = match declared {
+        Some(declared) => declared,
+        None => return Ok(None),
+    };
+
+    // Conversations started on this server are the collection themselves. Any
+    //  other local collection, like an outbox, can't be used as a conversation.
+    if let Some(collection) = context.entity_store.get(declared.to_owned(), true).await? {
+        if collection.is_owned(context) && collection.main().types.iter().any(|f| f == CONVERSATION)
+        {
+            return Ok(Some((declared.to_owned(), declared)));
+        }
+    }
+
+    let collection = conversation_collection(context, &declared);
+    Ok(Some((declared, collection)))
+}
+
+/// Ensures the conversation collection at `id` exists, creating it if it doesn't.
+async fn ensure_conversation(context: &mut Context<'_, '_>, id: &str) -> Result<(), TapError> {
+    if context
+        .entity_store
+        .get(id.to_owned(), true)
+        .await?
+        .is_none()
+    {
+        let server_base = context.server_base.to_owned();
+        let mut collection = build_collection(id, &server_base, None, context);
+        collection.main_mut().types.push(CONVERSATION.to_owned());
+        context
+            .entity_store
+            .put(id.to_owned(), &mut collection)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Assigns every created object to a conversation, and adds it to the ordered
+///  collection of that conversation, so entire threads can be read at once.
+///  Which of the objects a reader may see is left to the authorizer.
+pub struct ConversationHandler;
+
+#[async_trait::async_trait]
+impl ActivityHandler for ConversationHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        _inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
+        for pointer in &root.main()[as2!(object)] {
+            let id = match pointer {
+                Pointer::Id(id) => id,
+                _ => continue,
+            };
+
+            let mut object = match context.entity_store.get(id.to_owned(), false).await? {
+                Some(object) => object,
+                None => continue,
+            };
+
+            let is_owned = object.is_owned(context);
+            let (conversation, collection) = match find_conversation(context, &mut object).await? {
+                Some(found) => found,
+
+                // A new conversation started on this server.
+                None if is_owned => {
+                    let collection =
+                        assign_id(context, Some("conversation".to_owned()), None, 0).await?;
+                    (collection.to_owned(), collection)
+                }
+
+                // A remote conversation without any identifier, name it after its first post.
+                None => (
+                    object.id().to_owned(),
+                    conversation_collection(context, object.id()),
+                ),
+            };
+
+            ensure_conversation(context, &collection).await?;
+
+            let existing = context
+                .entity_store
+                .find_collection(collection.to_owned(), object.id().to_owned())
+                .await?;
+            if existing.items.is_empty() {
+                context
+                    .entity_store
+                    .insert_collection(collection.to_owned(), object.id().to_owned())
+                    .await?;
+            }
+
+            if assigned_conversation(&mut object).as_ref() == Some(&collection) {
+                continue;
+            }
+
+            if is_owned && object.main()[as2!(context)].is_empty() {
+                object.main_mut()[as2!(context)].push(Pointer::Id(conversation));
+            }
+
+            object.meta()[kroeg!(conversation)] = vec![Pointer::Id(collection)];
+            context
+                .entity_store
+                .put(object.id().to_owned(), &mut object)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{conversation_collection, ConversationHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use jsonld::nodemap::Pointer;
+    use kroeg_tap::{as2, kroeg, MessageHandler};
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![
+                object_under_test!(local "/local/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/local"];
+                }),
+                object_under_test!(local "/local" => {
+                    types => [as2!(Note)];
+                    as2!(to) => [as2!(Public)];
+                }),
+                object_under_test!(remote "/reply/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/reply"];
+                }),
+                object_under_test!(remote "/reply" => {
+                    types => [as2!(Note)];
+                    as2!(to) => [as2!(Public)];
+                    as2!(inReplyTo) => ["/local"];
+                }),
+                object_under_test!(remote "/remote/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/remote"];
+                }),
+                object_under_test!(remote "/remote" => {
+                    types => [as2!(Note)];
+                    as2!(to) => [as2!(Public)];
+                    as2!(context) => ["https://example.com/contexts/1"];
+                }),
+                object_under_test!(local "/local/actor/outbox" => {
+                    types => [as2!(OrderedCollection)];
+                }),
+                object_under_test!(remote "/outbox/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/outbox"];
+                }),
+                object_under_test!(remote "/outbox" => {
+                    types => [as2!(Note)];
+                    as2!(to) => [as2!(Public)];
+                    as2!(context) => ["/local/actor/outbox"];
+                }),
+                object_under_test!(remote "/direct/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/direct"];
+                }),
+                object_under_test!(remote "/direct" => {
+                    types => [as2!(Note)];
+                    as2!(to) => ["/local/actor"];
+                    as2!(inReplyTo) => ["/local"];
+                }),
+            ]),
+            (),
+        )
+    }
+
+    fn handle(store: &mut TestStore, id: &str) {
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        if let Err(e) = block_on(ConversationHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut id.to_owned(),
+        )) {
+            panic!("handler returned error: {}", e);
+        }
+    }
+
+    #[test]
+    fn threads_replies() {
+        let (mut store, _) = setup();
+        handle(&mut store, "/local/create");
+        handle(&mut store, "/reply/create");
+
+        let conversation = match &store.item("/local").unwrap().main()[as2!(context)] as &[_] {
+            [Pointer::Id(conversation)] => conversation.to_owned(),
+            _ => panic!("Handler did not assign a conversation to the local object"),
+        };
+
+        assert!(
+            store.contains(&conversation, "/local"),
+            "Handler did not add the local object to its conversation"
+        );
+        assert!(
+            store.contains(&conversation, "/reply"),
+            "Handler did not add the reply to the conversation"
+        );
+    }
+
+    #[test]
+    fn uses_declared_context() {
+        let (mut store, _) = setup();
+        handle(&mut store, "/remote/create");
+
+        let mut remote = store.item("/remote").unwrap().clone();
+        let collection = match &remote.meta()[kroeg!(conversation)] as &[_] {
+            [Pointer::Id(collection)] => collection.to_owned(),
+            _ => panic!("Handler did not record the conversation"),
+        };
+
+        let mut queue = ();
+        let expected =
+            conversation_collection(&store.context(&mut queue), "https://example.com/contexts/1");
+
+        assert_eq!(collection, expected, "Handler used the wrong collection");
+        assert!(
+            store.contains(&collection, "/remote"),
+            "Handler did not add the object to its conversation"
+        );
+    }
+
+    #[test]
+    fn threads_direct_messages() {
+        let (mut store, _) = setup();
+        handle(&mut store, "/local/create");
+        handle(&mut store, "/direct/create");
+
+        let conversation = match &store.item("/local").unwrap().main()[as2!(context)] as &[_] {
+            [Pointer::Id(conversation)] => conversation.to_owned(),
+            _ => panic!("Handler did not assign a conversation to the local object"),
+        };
+
+        assert!(
+            store.contains(&conversation, "/direct"),
+            "Handler did not add the direct message to its conversation"
+        );
+    }
+
+    #[test]
+    fn refuses_other_local_collections() {
+        let (mut store, _) = setup();
+        handle(&mut store, "/outbox/create");
+
+        assert!(
+            !store.contains("/local/actor/outbox", "/outbox"),
+            "Handler inserted a remote object into a local outbox"
+        );
+
+        let mut queue = ();
+        let expected = conversation_collection(&store.context(&mut queue), "/local/actor/outbox");
+        assert!(
+            store.contains(&expected, "/outbox"),
+            "Handler did not track the declared conversation separately"
+        );
+    }
+}
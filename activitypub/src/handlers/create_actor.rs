@@ -4,9 +4,175 @@ use serde_json::json;
 use serde_json::Value as JValue;
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 
-use kroeg_tap::{as2, assign_id, kroeg, ldp, sec, Context, MessageHandler, StoreItem};
+use url::percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
 
+use kroeg_tap::{
+    as2, assign_id, kroeg, ldp, sec, toot, Context, ErrorKind, MessageHandler, StoreItem, TapError,
+};
+
+#[derive(Debug)]
+pub enum CreateActorError {
+    InvalidUsername(String),
+    ReservedUsername(String),
+    UsernameTaken(String),
+}
+
+impl fmt::Display for CreateActorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CreateActorError::InvalidUsername(ref name) => {
+                write!(f, "The username {:?} is empty or contains whitespace", name)
+            }
+            CreateActorError::ReservedUsername(ref name) => {
+                write!(f, "The username {:?} is reserved", name)
+            }
+            CreateActorError::UsernameTaken(ref name) => {
+                write!(f, "The username {:?} is already taken", name)
+            }
+        }
+    }
+}
+
+impl Error for CreateActorError {}
+
+impl From<CreateActorError> for TapError {
+    fn from(error: CreateActorError) -> TapError {
+        let kind = match error {
+            CreateActorError::InvalidUsername(_) => ErrorKind::InvalidInput,
+            CreateActorError::ReservedUsername(_) => ErrorKind::InvalidInput,
+            CreateActorError::UsernameTaken(_) => ErrorKind::Conflict,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
+/// Usernames that are reserved by default.
+pub const DEFAULT_RESERVED_USERNAMES: &'static [&'static str] = &[
+    "abuse",
+    "admin",
+    "administrator",
+    "hostmaster",
+    "moderator",
+    "postmaster",
+    "root",
+    "support",
+    "webmaster",
+];
+
+/// Normalizes a username for comparison: usernames are unique ignoring case.
+///  Returns `None` if the username is empty or contains whitespace.
+fn normalize_username(username: &str) -> Option<String> {
+    let username = username.trim().to_lowercase();
+    if username.is_empty()
+        || username
+            .chars()
+            .any(|f| f.is_whitespace() || f.is_control())
+    {
+        None
+    } else {
+        Some(username)
+    }
+}
+
+/// The ID of the record of a (normalized) username in the username registry.
+fn username_record(context: &Context, username: &str) -> String {
+    format!(
+        "{}/usernames/{}/{}",
+        context.server_base,
+        context.instance_id,
+        utf8_percent_encode(username, PATH_SEGMENT_ENCODE_SET)
+    )
+}
+
+fn username_record_item(context: &Context, record_id: &str, username: &str) -> StoreItem {
+    let mut record = StoreItem::parse(
+        record_id,
+        &json!({
+            "@id": record_id,
+            "@type": [kroeg!(Username)],
+            as2!(name): [{"@value": username}]
+        }),
+    )
+    .unwrap();
+
+    record.meta()[kroeg!(instance)].push(Pointer::Value(Value {
+        value: context.instance_id.into(),
+        type_id: Some("http://www.w3.org/2001/XMLSchema#integer".to_owned()),
+        language: None,
+    }));
+
+    record
+}
+
+/// Reserves a username on this instance, so no actor can be created with it.
+///  `DEFAULT_RESERVED_USERNAMES` are always reserved. Fails if an actor
+///  already has the username.
+pub async fn reserve_username(
+    context: &mut Context<'_, '_>,
+    username: &str,
+) -> Result<(), TapError> {
+    let normalized = normalize_username(username)
+        .ok_or_else(|| CreateActorError::InvalidUsername(username.to_owned()))?;
+
+    let record_id = username_record(context, &normalized);
+    if let Some(mut record) = context.entity_store.get(record_id.to_owned(), true).await? {
+        if record.is_owned(context) && !record.meta()[kroeg!(actor)].is_empty() {
+            return Err(CreateActorError::UsernameTaken(username.to_owned()).into());
+        }
+    }
+
+    let mut record = username_record_item(context, &record_id, &normalized);
+    context.entity_store.put(record_id, &mut record).await
+}
+
+/// Records the username of an actor in the username registry, failing
+///  if it is reserved or belongs to another actor.
+async fn claim_username(context: &mut Context<'_, '_>, person: &StoreItem) -> Result<(), TapError> {
+    let username = match person.main()[as2!(preferredUsername)].first() {
+        Some(Pointer::Value(Value {
+            value: JValue::String(username),
+            ..
+        })) => username.to_owned(),
+        _ => return Ok(()),
+    };
+
+    let normalized = normalize_username(&username)
+        .ok_or_else(|| CreateActorError::InvalidUsername(username.to_owned()))?;
+
+    if DEFAULT_RESERVED_USERNAMES.contains(&(&normalized as &str)) {
+        return Err(CreateActorError::ReservedUsername(username).into());
+    }
+
+    let record_id = username_record(context, &normalized);
+    if let Some(mut record) = context.entity_store.get(record_id.to_owned(), true).await? {
+        if record.is_owned(context) {
+            let actors = &record.meta()[kroeg!(actor)];
+            if actors.is_empty() {
+                return Err(CreateActorError::ReservedUsername(username).into());
+            }
+
+            if !actors.contains(&Pointer::Id(person.id().to_owned())) {
+                return Err(CreateActorError::UsernameTaken(username).into());
+            }
+        }
+    }
+
+    let mut record = username_record_item(context, &record_id, &normalized);
+    record.meta()[kroeg!(actor)].push(Pointer::Id(person.id().to_owned()));
+
+    context.entity_store.put(record_id, &mut record).await
+}
+
+/// Creates the collections and key of new actors, and claims their username.
+///
+/// Usernames are unique per instance, ignoring case: every username is
+///  recorded in the registry at `{server_base}/usernames/{instance_id}/`.
+///  Creating an actor with a username that is recorded for another actor, or
+///  that is reserved with `reserve_username`, fails. Only the registry is
+///  checked, so actors created before it existed need a record of their own.
 pub struct CreateActorHandler;
 
 fn create_key_obj(owner: &str) -> Result<StoreItem, Box<dyn Error + Send + Sync + 'static>> {
@@ -38,7 +204,12 @@ fn create_key_obj(owner: &str) -> Result<StoreItem, Box<dyn Error + Send + Sync
     Ok(storeitem)
 }
 
-fn build_collection(id: &str, owned: &str, boxtype: Option<&str>, context: &Context) -> StoreItem {
+pub(crate) fn build_collection(
+    id: &str,
+    owned: &str,
+    boxtype: Option<&str>,
+    context: &Context,
+) -> StoreItem {
     let mut item = StoreItem::parse(
         id,
         &json!({
@@ -62,22 +233,55 @@ fn build_collection(id: &str, owned: &str, boxtype: Option<&str>, context: &Cont
     item
 }
 
-// inbox, outbox, following, followers, liked
+/// Ensures an owned collection exists at `id`, creating it as part of `owned` if it doesn't.
+pub(crate) async fn ensure_collection(
+    context: &mut Context<'_, '_>,
+    id: &str,
+    owned: &str,
+) -> Result<(), TapError> {
+    if context
+        .entity_store
+        .get(id.to_owned(), true)
+        .await?
+        .is_none()
+    {
+        let mut collection = build_collection(id, owned, None, context);
+        context
+            .entity_store
+            .put(id.to_owned(), &mut collection)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// The collection of objects an actor has pinned to their profile.
+pub const FEATURED: &'static str = toot!(featured);
+
+/// The home timeline of an actor, containing the posts of everyone they follow.
+pub const HOME_TIMELINE: &'static str = kroeg!(homeTimeline);
+
+// inbox, outbox, following, followers, liked, featured, home timeline
 const COLLECTIONS: &'static [(&'static str, &'static str, Option<&'static str>)] = &[
     ("inbox", ldp!(inbox), Some(ldp!(inbox))),
     ("outbox", as2!(outbox), Some(as2!(outbox))),
     ("following", as2!(following), None),
     ("followers", as2!(followers), None),
     ("liked", as2!(liked), None),
+    ("featured", FEATURED, None),
+    ("home", HOME_TIMELINE, None),
 ];
 
 async fn add_all_collections(
     context: &mut Context<'_, '_>,
     item: &mut StoreItem,
-) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+) -> Result<(), TapError> {
     for (typ, key, boxtype) in COLLECTIONS {
         if !item.main()[key].is_empty() {
-            return Err(format!("predicate {} already has value while creating user", key).into());
+            return Err(TapError::new(
+                ErrorKind::InvalidInput,
+                format!("predicate {} already has value while creating user", key),
+            ));
         }
 
         let collection_id = assign_id(
@@ -99,7 +303,10 @@ async fn add_all_collections(
     }
 
     if item.main()[sec!(publicKey)].len() != 0 {
-        return Err("predicate publicKey already has value while creating user".into());
+        return Err(TapError::new(
+            ErrorKind::InvalidInput,
+            "predicate publicKey already has value while creating user",
+        ));
     }
 
     let mut key = create_key_obj(item.id())?;
@@ -121,7 +328,7 @@ impl MessageHandler for CreateActorHandler {
         context: &mut Context<'_, '_>,
         _inbox: &mut String,
         elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    ) -> Result<(), TapError> {
         let elem = match context.entity_store.get(elem.to_owned(), false).await? {
             Some(elem) => elem,
             None => return Ok(()),
@@ -142,6 +349,150 @@ impl MessageHandler for CreateActorHandler {
             return Ok(());
         };
 
+        claim_username(context, &person).await?;
         add_all_collections(context, &mut person).await
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{reserve_username, CreateActorError, CreateActorHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use jsonld::nodemap::{Pointer, Value};
+    use kroeg_tap::{as2, kroeg, ldp, MessageHandler, StoreItem, TapError};
+
+    fn person(id: &str, username: &str) -> StoreItem {
+        let mut person = object_under_test!(local id => {
+            types => [as2!(Person)];
+        });
+        person.main_mut()[as2!(preferredUsername)].push(Pointer::Value(Value {
+            value: username.into(),
+            type_id: None,
+            language: None,
+        }));
+
+        person
+    }
+
+    fn setup() -> (TestStore, ()) {
+        let mut record = object_under_test!(local "/usernames/1/carol" => {
+            types => [kroeg!(Username)];
+        });
+        record.meta()[kroeg!(actor)].push(Pointer::Id("/~carol".to_owned()));
+
+        (
+            TestStore::new(vec![
+                record,
+                person("/~carol", "carol"),
+                person("/~carol-1", "Carol"),
+                person("/~admin", "Admin"),
+                person("/~bob", "Bob"),
+                person("/~bob-smith", "bob-smith"),
+                person("/~bob.smith", "bob.smith"),
+            ]),
+            (),
+        )
+    }
+
+    fn create(store: &mut TestStore, id: &str) -> Result<(), TapError> {
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        block_on(CreateActorHandler.handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut id.to_owned(),
+        ))
+    }
+
+    fn assert_refused(result: Result<(), TapError>, reserved: bool) {
+        match result {
+            Ok(()) => panic!("handler created an actor with an unavailable username"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    CreateActorError::ReservedUsername(_) if reserved => { /* ok! */ }
+                    CreateActorError::UsernameTaken(_) if !reserved => { /* ok! */ }
+                    e => panic!("handler refused actor for wrong reason: {}", e),
+                },
+
+                Err(e) => panic!("handler refused actor: {}", e),
+            },
+        }
+    }
+
+    #[test]
+    fn refuses_taken_username() {
+        let (mut store, _) = setup();
+
+        assert_refused(create(&mut store, "/~carol-1"), false);
+    }
+
+    #[test]
+    fn refuses_reserving_taken_username() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(reserve_username(&mut context, "Carol")) {
+            Ok(()) => panic!("reserved the username of an existing actor"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    CreateActorError::UsernameTaken(_) => { /* ok! */ }
+                    e => panic!("refused reservation for wrong reason: {}", e),
+                },
+
+                Err(e) => panic!("refused reservation: {}", e),
+            },
+        }
+
+        let record = store.item("/usernames/1/carol").unwrap();
+        assert_eq!(
+            &record.clone().meta()[kroeg!(actor)],
+            &vec![Pointer::Id("/~carol".to_owned())],
+            "Reservation took the username from its actor"
+        );
+    }
+
+    #[test]
+    fn refuses_reserved_username() {
+        let (mut store, mut queue) = setup();
+
+        assert_refused(create(&mut store, "/~admin"), true);
+
+        let mut context = store.context(&mut queue);
+        block_on(reserve_username(&mut context, "BOB")).expect("failed to reserve username");
+        assert_refused(create(&mut store, "/~bob"), true);
+    }
+
+    #[test]
+    fn registers_username() {
+        let (mut store, _) = setup();
+
+        create(&mut store, "/~bob").expect("handler refused actor");
+
+        let record = store
+            .item("/usernames/1/bob")
+            .expect("Username was not recorded");
+        assert_eq!(
+            &record.clone().meta()[kroeg!(actor)],
+            &vec![Pointer::Id("/~bob".to_owned())],
+            "Username was recorded for the wrong actor"
+        );
+        assert!(
+            !store.item("/~bob").unwrap().main()[ldp!(inbox)].is_empty(),
+            "Handler did not create the collections of the actor"
+        );
+    }
+
+    #[test]
+    fn keys_on_exact_username() {
+        let (mut store, _) = setup();
+
+        create(&mut store, "/~bob-smith").expect("handler refused actor");
+        create(&mut store, "/~bob.smith").expect("handler refused similar username");
+
+        assert!(store.item("/usernames/1/bob-smith").is_some());
+        assert!(store.item("/usernames/1/bob.smith").is_some());
+    }
+}
@@ -0,0 +1,282 @@
+use jsonld::nodemap::{Pointer, Value};
+use openssl::sha::sha256;
+use serde_json::json;
+use serde_json::Value as JValue;
+use std::error::Error;
+use std::fmt;
+
+use kroeg_tap::{kroeg, ActivityHandler, Context, ErrorKind, StoreItem, TapError};
+
+#[derive(Debug)]
+pub enum DeduplicateError {
+    AlreadyProcessed,
+    ReplayedWithDifferentContent,
+}
+
+impl fmt::Display for DeduplicateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DeduplicateError::AlreadyProcessed => {
+                write!(f, "This activity has already been processed")
+            }
+            DeduplicateError::ReplayedWithDifferentContent => write!(
+                f,
+                "This activity has already been processed, but with different contents"
+            ),
+        }
+    }
+}
+
+impl Error for DeduplicateError {}
+
+impl From<DeduplicateError> for TapError {
+    fn from(error: DeduplicateError) -> TapError {
+        let kind = match error {
+            DeduplicateError::AlreadyProcessed => ErrorKind::AlreadyHandled,
+            DeduplicateError::ReplayedWithDifferentContent => ErrorKind::Conflict,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|f| format!("{:02x}", f)).collect()
+}
+
+/// Hashes the contents of an activity, including any embedded objects, but
+///  not the metadata of the store.
+fn payload_hash(activity: &StoreItem) -> String {
+    let mut ids: Vec<_> = activity.ids().filter(|f| *f != kroeg!(meta)).collect();
+    ids.sort();
+
+    let payload: Vec<_> = ids
+        .into_iter()
+        .map(|id| activity.sub(id).unwrap().clone().into_json())
+        .collect();
+    let payload = JValue::Array(payload).to_string();
+
+    to_hex(&sha256(payload.as_bytes()))
+}
+
+/// The ID of the record of an activity having been processed for an inbox.
+fn processed_record(context: &Context, inbox: &str, id: &str) -> String {
+    let key = sha256(format!("{}\n{}", inbox, id).as_bytes());
+
+    format!("{}/processed/{}", context.server_base, to_hex(&key[..16]))
+}
+
+/// Ensures every activity is only processed once per inbox, by recording the
+///  activities that have been processed, and the hash of their contents.
+///
+/// This handler should run before any other inbox handler. When an activity is
+///  received again, it returns `DeduplicateError::AlreadyProcessed` of kind
+///  `ErrorKind::AlreadyHandled`, which stops a `Pipeline` and is treated as a
+///  successful delivery. A different activity with a reused ID is refused
+///  with `DeduplicateError::ReplayedWithDifferentContent`.
+pub struct DeduplicateHandler;
+
+#[async_trait::async_trait]
+impl ActivityHandler for DeduplicateHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &str,
+        activity: &StoreItem,
+    ) -> Result<(), TapError> {
+        let hash = payload_hash(activity);
+        let record_id = processed_record(context, inbox, activity.id());
+
+        if let Some(mut record) = context.entity_store.get(record_id.to_owned(), true).await? {
+            let matches = match record.meta()[kroeg!(hash)].first() {
+                Some(Pointer::Value(Value {
+                    value: JValue::String(recorded),
+                    ..
+                })) => recorded == &hash,
+                _ => false,
+            };
+
+            return Err(if matches {
+                DeduplicateError::AlreadyProcessed
+            } else {
+                DeduplicateError::ReplayedWithDifferentContent
+            }
+            .into());
+        }
+
+        let mut record = StoreItem::parse(
+            &record_id,
+            &json!({
+                "@id": record_id,
+                "@type": [kroeg!(ProcessedActivity)]
+            }),
+        )
+        .unwrap();
+
+        let meta = record.meta();
+        meta[kroeg!(instance)].push(Pointer::Value(Value {
+            value: context.instance_id.into(),
+            type_id: Some("http://www.w3.org/2001/XMLSchema#integer".to_owned()),
+            language: None,
+        }));
+        meta[kroeg!(activity)].push(Pointer::Id(activity.id().to_owned()));
+        meta[kroeg!(hash)].push(Pointer::Value(Value {
+            value: JValue::String(hash),
+            type_id: None,
+            language: None,
+        }));
+
+        context.entity_store.put(record_id, &mut record).await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DeduplicateError, DeduplicateHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler, StoreItem, TapError};
+    use serde_json::json;
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![object_under_test!(remote "/like" => {
+                types => [as2!(Like)];
+                as2!(object) => ["/object"];
+            })]),
+            (),
+        )
+    }
+
+    fn assert_refused(result: Result<(), TapError>, replayed: bool) {
+        match result {
+            Ok(()) => panic!("handler accepted duplicate activity"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    DeduplicateError::AlreadyProcessed if !replayed => { /* ok! */ }
+                    DeduplicateError::ReplayedWithDifferentContent if replayed => { /* ok! */ }
+                    e => panic!("handler refused activity for wrong reason: {}", e),
+                },
+
+                Err(e) => panic!("handler refused activity: {}", e),
+            },
+        }
+    }
+
+    #[test]
+    fn refuses_repeat() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        block_on(DeduplicateHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/like".to_owned(),
+        ))
+        .expect("handler refused first delivery");
+
+        assert_refused(
+            block_on(DeduplicateHandler.handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut "/like".to_owned(),
+            )),
+            false,
+        );
+    }
+
+    #[test]
+    fn separates_inboxes() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        for inbox in &["/inbox/a", "/inbox/b"] {
+            block_on(DeduplicateHandler.handle(
+                &mut context,
+                &mut inbox.to_string(),
+                &mut "/like".to_owned(),
+            ))
+            .expect("handler refused delivery to another inbox");
+        }
+    }
+
+    #[test]
+    fn refuses_replay_with_different_content() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        block_on(DeduplicateHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/like".to_owned(),
+        ))
+        .expect("handler refused first delivery");
+
+        let mut changed = object_under_test!(remote "/like" => {
+            types => [as2!(Like)];
+            as2!(object) => ["/other"];
+        });
+        block_on(context.entity_store.put("/like".to_owned(), &mut changed)).unwrap();
+
+        assert_refused(
+            block_on(DeduplicateHandler.handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut "/like".to_owned(),
+            )),
+            true,
+        );
+    }
+
+    #[test]
+    fn refuses_replay_with_different_embedded_object() {
+        let create = |content: &str| {
+            StoreItem::parse(
+                "/create",
+                &json!({
+                    "@id": "/create",
+                    "@type": [as2!(Create)],
+                    as2!(object): [{
+                        "@type": [as2!(Note)],
+                        as2!(content): [{"@value": content}]
+                    }]
+                }),
+            )
+            .unwrap()
+        };
+
+        let mut store = TestStore::new(vec![create("first")]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        block_on(DeduplicateHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("handler refused first delivery");
+
+        block_on(
+            context
+                .entity_store
+                .put("/create".to_owned(), &mut create("second")),
+        )
+        .unwrap();
+
+        assert_refused(
+            block_on(DeduplicateHandler.handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut "/create".to_owned(),
+            )),
+            true,
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use jsonld::nodemap::{Entity, Pointer, Value};
+use serde_json::Value as JValue;
+
+use kroeg_tap::{as2, ActivityHandler, Context, StoreItem, TapError, Visibility};
+
+use super::create_actor::ensure_collection;
+
+/// Normalizes the name of a hashtag, e.g. `#Kroeg` becomes `kroeg`.
+///
+/// Returns `None` if nothing usable is left of the name.
+pub fn normalize_hashtag(name: &str) -> Option<String> {
+    let name: String = name
+        .trim()
+        .trim_start_matches('#')
+        .chars()
+        .filter(|ch| ch.is_alphanumeric() || *ch == '_')
+        .flat_map(|ch| ch.to_lowercase())
+        .collect();
+
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// The ID of the server-owned collection containing all objects tagged with a
+///  (normalized) hashtag.
+pub fn hashtag_collection(context: &Context, name: &str) -> String {
+    format!("{}/tags/{}", context.server_base, name)
+}
+
+fn hashtag_name(tag: &Entity) -> Option<String> {
+    if !tag.types.iter().any(|f| f == as2!(Hashtag)) {
+        return None;
+    }
+
+    match tag[as2!(name)].first() {
+        Some(Pointer::Value(Value {
+            value: JValue::String(name),
+            ..
+        })) => normalize_hashtag(name),
+        _ => None,
+    }
+}
+
+pub struct HashtagHandler;
+
+#[async_trait::async_trait]
+impl ActivityHandler for HashtagHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        _inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
+        for pointer in &root.main()[as2!(object)] {
+            let id = match pointer {
+                Pointer::Id(id) => id,
+                _ => continue,
+            };
+
+            let object = match context.entity_store.get(id.to_owned(), false).await? {
+                Some(object) => object,
+                None => continue,
+            };
+
+            // Hashtag collections are public, so only index public objects.
+            if Visibility::of(object.main(), &[]) != Visibility::Public {
+                continue;
+            }
+
+            let mut names = Vec::new();
+            for tag in &object.main()[as2!(tag)] {
+                let tag = match tag {
+                    Pointer::Id(tag) => tag,
+                    _ => continue,
+                };
+
+                // Tags are usually embedded in the object, but may also be stored separately.
+                let name = match object.sub(tag) {
+                    Some(tag) => hashtag_name(tag),
+                    None => match context.entity_store.get(tag.to_owned(), false).await? {
+                        Some(tag) => hashtag_name(tag.main()),
+                        None => None,
+                    },
+                };
+
+                if let Some(name) = name {
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                }
+            }
+
+            let server_base = context.server_base.to_owned();
+            for name in names {
+                let collection = hashtag_collection(context, &name);
+                ensure_collection(context, &collection, &server_base).await?;
+
+                context
+                    .entity_store
+                    .insert_collection(collection, object.id().to_owned())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{normalize_hashtag, HashtagHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler, StoreItem};
+    use serde_json::json;
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![
+                object_under_test!(remote "/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/note"];
+                }),
+                object_under_test!(remote "/like" => {
+                    types => [as2!(Like)];
+                    as2!(object) => ["/note"];
+                }),
+                object_under_test!(remote "/direct/create" => {
+                    types => [as2!(Create)];
+                    as2!(object) => ["/direct"];
+                }),
+                StoreItem::parse(
+                    "/direct",
+                    &json!({
+                        "@id": "/direct",
+                        "@type": [as2!(Note)],
+                        as2!(to): [{"@id": "/someone"}],
+                        as2!(tag): [{
+                            "@type": [as2!(Hashtag)],
+                            as2!(name): [{"@value": "#Kroeg"}]
+                        }]
+                    }),
+                )
+                .unwrap(),
+                StoreItem::parse(
+                    "/note",
+                    &json!({
+                        "@id": "/note",
+                        "@type": [as2!(Note)],
+                        as2!(to): [{"@id": as2!(Public)}],
+                        as2!(tag): [
+                            {
+                                "@type": [as2!(Hashtag)],
+                                as2!(name): [{"@value": "#Kroeg"}]
+                            },
+                            {
+                                "@type": [as2!(Mention)],
+                                as2!(name): [{"@value": "@someone"}]
+                            }
+                        ]
+                    }),
+                )
+                .unwrap(),
+            ]),
+            (),
+        )
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize_hashtag("#Kroeg"), Some("kroeg".to_owned()));
+        assert_eq!(
+            normalize_hashtag("#tap_dance!"),
+            Some("tap_dance".to_owned())
+        );
+        assert_eq!(normalize_hashtag("#"), None);
+    }
+
+    #[test]
+    fn indexes_hashtags() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(HashtagHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        )) {
+            Ok(()) => {
+                assert!(
+                    store.has_read_all(&["/create", "/note", "/tags/kroeg"]),
+                    "Handler did not read all the expected objects"
+                );
+                assert!(
+                    store.contains("/tags/kroeg", "/note"),
+                    "Handler did not index the hashtag"
+                );
+                assert!(
+                    !store.contains("/tags/someone", "/note"),
+                    "Handler indexed a mention as hashtag"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn ignores_non_create() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(HashtagHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/like".to_owned(),
+        )) {
+            Ok(()) => {
+                assert!(
+                    !store.contains("/tags/kroeg", "/note"),
+                    "Handler indexed hashtags of a Like"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn ignores_direct_messages() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(HashtagHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/direct/create".to_owned(),
+        )) {
+            Ok(()) => {
+                assert!(
+                    !store.contains("/tags/kroeg", "/direct"),
+                    "Handler indexed a direct message"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+}
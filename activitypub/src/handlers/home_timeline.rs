@@ -0,0 +1,264 @@
+use jsonld::nodemap::Pointer;
+
+use kroeg_tap::{as2, ActivityHandler, Context, StoreError, StoreItem, TapError};
+
+use super::create_actor::HOME_TIMELINE;
+
+/// The companion collection of a home timeline, tracking which objects are
+///  already in it.
+fn seen_objects(home: &str) -> String {
+    format!("{}#objects", home)
+}
+
+/// Adds an activity to a home timeline, unless it announces an object that is
+///  already present.
+async fn add_to_home(
+    context: &mut Context<'_, '_>,
+    home: &str,
+    activity: &str,
+    objects: &[Pointer],
+    is_announce: bool,
+) -> Result<(), StoreError> {
+    let existing = context
+        .entity_store
+        .find_collection(home.to_owned(), activity.to_owned())
+        .await?;
+    if !existing.items.is_empty() {
+        return Ok(());
+    }
+
+    let seen = seen_objects(home);
+    let mut unseen = Vec::new();
+    for object in objects {
+        if let Pointer::Id(object) = object {
+            let existing = context
+                .entity_store
+                .find_collection(seen.to_owned(), object.to_owned())
+                .await?;
+
+            if existing.items.is_empty() {
+                unseen.push(object.to_owned());
+            }
+        }
+    }
+
+    if is_announce && unseen.is_empty() {
+        return Ok(());
+    }
+
+    for object in unseen {
+        context
+            .entity_store
+            .insert_collection(seen.to_owned(), object)
+            .await?;
+    }
+
+    context
+        .entity_store
+        .insert_collection(home.to_owned(), activity.to_owned())
+        .await
+}
+
+/// Builds the home timeline of local actors. In the outbox (`true`), the
+///  posts of the actor themselves get added, and in the inbox (`false`), the
+///  posts of everyone the actor follows.
+pub struct HomeTimelineHandler(pub bool);
+
+#[async_trait::async_trait]
+impl ActivityHandler for HomeTimelineHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create), as2!(Announce)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
+        let is_announce = root.main().types.iter().any(|f| f == as2!(Announce));
+
+        let sender = match &root.main()[as2!(actor)] as &[Pointer] {
+            [Pointer::Id(sender)] => sender.to_owned(),
+            _ => return Ok(()),
+        };
+
+        let local_post = self.0;
+        let owner = if local_post {
+            context.user.subject.to_owned()
+        } else {
+            let inbox = match context.entity_store.get(inbox.to_owned(), true).await? {
+                Some(inbox) => inbox,
+                None => return Ok(()),
+            };
+
+            match &inbox.main()[as2!(attributedTo)] as &[Pointer] {
+                [Pointer::Id(owner)] => owner.to_owned(),
+                _ => return Ok(()),
+            }
+        };
+
+        if local_post && sender != owner {
+            return Ok(());
+        }
+
+        let owner = match context.entity_store.get(owner, true).await? {
+            Some(owner) if owner.is_owned(context) => owner,
+            _ => return Ok(()),
+        };
+
+        let home = match &owner.main()[HOME_TIMELINE] as &[Pointer] {
+            [Pointer::Id(home)] => home.to_owned(),
+            _ => return Ok(()),
+        };
+
+        if !local_post {
+            let following = match &owner.main()[as2!(following)] as &[Pointer] {
+                [Pointer::Id(following)] => following.to_owned(),
+                _ => return Ok(()),
+            };
+
+            let followed = context
+                .entity_store
+                .find_collection(following, sender)
+                .await?;
+            if followed.items.is_empty() {
+                return Ok(());
+            }
+        }
+
+        add_to_home(
+            context,
+            &home,
+            root.id(),
+            &root.main()[as2!(object)],
+            is_announce,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HomeTimelineHandler;
+    use crate::handlers::HOME_TIMELINE;
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler};
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![
+                object_under_test!(local "/subject" => {
+                    types => [as2!(Person)];
+                    as2!(following) => ["/subject/following"];
+                    HOME_TIMELINE => ["/subject/home"];
+                }),
+                object_under_test!(local "/inbox" => {
+                    types => [as2!(OrderedCollection)];
+                    as2!(attributedTo) => ["/subject"];
+                }),
+                object_under_test!(local "/own" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/subject"];
+                    as2!(object) => ["/own/note"];
+                }),
+                object_under_test!(remote "/followed/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/followed"];
+                    as2!(object) => ["/followed/note"];
+                }),
+                object_under_test!(remote "/followed/announce" => {
+                    types => [as2!(Announce)];
+                    as2!(actor) => ["/followed"];
+                    as2!(object) => ["/followed/note"];
+                }),
+                object_under_test!(remote "/stranger/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/stranger"];
+                    as2!(object) => ["/stranger/note"];
+                }),
+            ]),
+            (),
+        )
+    }
+
+    fn follow(store: &mut TestStore) {
+        let mut queue = ();
+        let context = store.context(&mut queue);
+
+        block_on(
+            context
+                .entity_store
+                .insert_collection("/subject/following".to_owned(), "/followed".to_owned()),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn adds_own_posts() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(HomeTimelineHandler(true).handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/own".to_owned(),
+        )) {
+            Ok(()) => assert!(
+                store.contains("/subject/home", "/own"),
+                "Handler did not add own post"
+            ),
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn adds_only_followed_posts() {
+        let (mut store, mut queue) = setup();
+        follow(&mut store);
+        let mut context = store.context(&mut queue);
+
+        for post in &["/followed/create", "/stranger/create"] {
+            block_on(HomeTimelineHandler(false).handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut post.to_string(),
+            ))
+            .expect("handler returned error");
+        }
+
+        assert!(
+            store.contains("/subject/home", "/followed/create"),
+            "Handler did not add post of followed actor"
+        );
+        assert!(
+            !store.contains("/subject/home", "/stranger/create"),
+            "Handler added post of actor that isn't followed"
+        );
+    }
+
+    #[test]
+    fn skips_announce_of_present_object() {
+        let (mut store, mut queue) = setup();
+        follow(&mut store);
+        let mut context = store.context(&mut queue);
+
+        for post in &["/followed/create", "/followed/announce"] {
+            block_on(HomeTimelineHandler(false).handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut post.to_string(),
+            ))
+            .expect("handler returned error");
+        }
+
+        assert!(
+            !store.contains("/subject/home", "/followed/announce"),
+            "Handler added announce of object already in the timeline"
+        );
+    }
+}
@@ -2,6 +2,22 @@
 mod verify_required;
 pub use self::verify_required::*;
 
+// Adds objects to the collections of the hashtags they are tagged with.
+mod hashtag;
+pub use self::hashtag::*;
+
+// Adds public posts to the local or federated timeline.
+mod timeline;
+pub use self::timeline::*;
+
+// Adds own posts, and posts of followed actors, to the home timeline.
+mod home_timeline;
+pub use self::home_timeline::*;
+
+// Adds created objects to the collection of the conversation they are part of.
+mod conversation;
+pub use self::conversation::*;
+
 // --- Outbox only: ---
 
 // Handles wrapping non-activities with a Create activity.
@@ -20,20 +36,37 @@ pub use self::client_create::*;
 mod client_like;
 pub use self::client_like::*;
 
+// Adds objects to and removes them from collections owned by the actor, e.g. featured.
+mod client_add_remove;
+pub use self::client_add_remove::*;
+
 // Undoes Like/Follow/Accept
 mod client_undo;
 pub use self::client_undo::*;
 
 // --- Inbox only: ---
 
+// Refuses activities that have already been processed. Should run first.
+mod deduplicate;
+pub use self::deduplicate::*;
+
 // Adds object to replies if inReplyTo is an owned object.
 mod server_create;
 pub use self::server_create::*;
 
 // Adds object to likes and/or shares if object being liked/announced is owned.
+// Likes with content and EmojiReacts are grouped per emoji instead.
 mod server_like;
 pub use self::server_like::*;
 
+// Undoes Like/Announce/EmojiReact on owned objects.
+mod server_undo;
+pub use self::server_undo::*;
+
+// Tallies votes on owned questions.
+mod server_question;
+pub use self::server_question::*;
+
 // Handles follows, and their accept/rejects.
 mod server_follow;
 pub use self::server_follow::*;
@@ -2,12 +2,13 @@ use jsonld::nodemap::Pointer;
 use std::error::Error;
 use std::fmt;
 
-use kroeg_tap::{as2, Context, MessageHandler};
+use kroeg_tap::{as2, ActivityHandler, Context, ErrorKind, StoreItem, TapError};
 
 #[derive(Debug)]
 pub enum ServerCreateError {
     FailedToRetrieve,
     MissingObject,
+    MissingInbox,
 }
 
 impl fmt::Display for ServerCreateError {
@@ -18,36 +19,46 @@ impl fmt::Display for ServerCreateError {
                 f,
                 "The as:object predicate is missing or occurs more than once"
             ),
+            ServerCreateError::MissingInbox => {
+                write!(f, "The inbox the activity was delivered to does not exist")
+            }
         }
     }
 }
 
 impl Error for ServerCreateError {}
 
+impl From<ServerCreateError> for TapError {
+    fn from(error: ServerCreateError) -> TapError {
+        let kind = match error {
+            ServerCreateError::FailedToRetrieve => ErrorKind::Remote,
+            ServerCreateError::MissingObject => ErrorKind::InvalidInput,
+            ServerCreateError::MissingInbox => ErrorKind::NotFound,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
 pub struct ServerCreateHandler;
 
 #[async_trait::async_trait]
-impl MessageHandler for ServerCreateHandler {
-    async fn handle(
+impl ActivityHandler for ServerCreateHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create)]
+    }
+
+    async fn handle_activity(
         &self,
         context: &mut Context<'_, '_>,
-        inbox: &mut String,
-        elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        let root = match context.entity_store.get(elem.to_owned(), false).await? {
-            Some(root) => root,
-            None => return Ok(()),
-        };
-
-        if !root.main().types.iter().any(|f| f == as2!(Create)) {
-            return Ok(());
-        }
-
+        inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
         let inbox = context
             .entity_store
             .get(inbox.to_owned(), true)
             .await?
-            .unwrap();
+            .ok_or(ServerCreateError::MissingInbox)?;
         let attributed_to = &inbox.main()[as2!(attributedTo)];
 
         if attributed_to.is_empty() {
@@ -98,7 +109,7 @@ impl MessageHandler for ServerCreateHandler {
 
 #[cfg(test)]
 mod test {
-    use super::ServerCreateHandler;
+    use super::{ServerCreateError, ServerCreateHandler};
     use crate::test::TestStore;
     use crate::{handle_object_pair, object_under_test};
     use async_std::task::block_on;
@@ -180,4 +191,26 @@ mod test {
             Err(e) => panic!("handler returned error: {}", e),
         }
     }
+
+    #[test]
+    fn refuses_missing_inbox() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerCreateHandler.handle(
+            &mut context,
+            &mut "/missing".to_owned(),
+            &mut "/local/create".to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted an activity for a missing inbox"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    ServerCreateError::MissingInbox => { /* ok! */ }
+                    e => panic!("handler refused activity for wrong reason: {}", e),
+                },
+
+                Err(e) => panic!("handler refused activity: {}", e),
+            },
+        }
+    }
 }
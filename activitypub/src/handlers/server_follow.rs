@@ -1,36 +1,54 @@
 use jsonld::nodemap::Pointer;
 use std::error::Error;
+use std::fmt;
 
-use kroeg_tap::{as2, Context, MessageHandler};
+use kroeg_tap::{as2, ActivityHandler, Context, ErrorKind, StoreItem, TapError};
+
+#[derive(Debug)]
+pub enum ServerFollowError {
+    MissingInbox,
+}
+
+impl fmt::Display for ServerFollowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerFollowError::MissingInbox => {
+                write!(f, "The inbox the activity was delivered to does not exist")
+            }
+        }
+    }
+}
+
+impl Error for ServerFollowError {}
+
+impl From<ServerFollowError> for TapError {
+    fn from(error: ServerFollowError) -> TapError {
+        TapError::new(ErrorKind::NotFound, error)
+    }
+}
 
 pub struct ServerFollowHandler;
 
 #[async_trait::async_trait]
-impl MessageHandler for ServerFollowHandler {
-    async fn handle(
+impl ActivityHandler for ServerFollowHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Follow), as2!(Accept), as2!(Reject)]
+    }
+
+    async fn handle_activity(
         &self,
         context: &mut Context<'_, '_>,
-        inbox: &mut String,
-        elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        let root = match context.entity_store.get(elem.to_owned(), false).await? {
-            Some(root) => root,
-            None => return Ok(()),
-        };
-
-        let is_follow = root.main().types.iter().any(|f| f == as2!(Follow));
+        inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
         let is_accept = root.main().types.iter().any(|f| f == as2!(Accept));
         let is_reject = root.main().types.iter().any(|f| f == as2!(Reject));
 
-        if !is_follow && !is_accept && !is_reject {
-            return Ok(());
-        }
-
         let inbox = context
             .entity_store
             .get(inbox.to_owned(), true)
             .await?
-            .unwrap();
+            .ok_or(ServerFollowError::MissingInbox)?;
         let attributed_to = &inbox.main()[as2!(attributedTo)];
 
         if attributed_to.is_empty() {
@@ -97,23 +115,29 @@ impl MessageHandler for ServerFollowHandler {
                         };
 
                         if reject {
-                            let _ = context.entity_store.remove_collection(
-                                following.to_owned(),
-                                context.user.subject.to_owned(),
-                            );
+                            context
+                                .entity_store
+                                .remove_collection(
+                                    following.to_owned(),
+                                    context.user.subject.to_owned(),
+                                )
+                                .await?;
                         } else {
-                            let _ = context.entity_store.insert_collection(
-                                following.to_owned(),
-                                context.user.subject.to_owned(),
-                            );
+                            context
+                                .entity_store
+                                .insert_collection(
+                                    following.to_owned(),
+                                    context.user.subject.to_owned(),
+                                )
+                                .await?;
                         }
                     }
                 }
 
                 if reject {
-                    item.meta()[as2!(Reject)].push(Pointer::Id(elem.to_owned()));
+                    item.meta()[as2!(Reject)].push(Pointer::Id(root.id().to_owned()));
                 } else {
-                    item.meta()[as2!(Accept)].push(Pointer::Id(elem.to_owned()));
+                    item.meta()[as2!(Accept)].push(Pointer::Id(root.id().to_owned()));
                 }
 
                 context
@@ -126,3 +150,44 @@ impl MessageHandler for ServerFollowHandler {
         return Ok(());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{ServerFollowError, ServerFollowHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler};
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![object_under_test!(remote "/follow" => {
+                types => [as2!(Follow)];
+                as2!(actor) => ["/remote"];
+                as2!(object) => ["/actor"];
+            })]),
+            (),
+        )
+    }
+
+    #[test]
+    fn refuses_missing_inbox() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerFollowHandler.handle(
+            &mut context,
+            &mut "/missing".to_owned(),
+            &mut "/follow".to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted an activity for a missing inbox"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    ServerFollowError::MissingInbox => { /* ok! */ }
+                },
+
+                Err(e) => panic!("handler refused activity: {}", e),
+            },
+        }
+    }
+}
@@ -1,35 +1,223 @@
-use jsonld::nodemap::Pointer;
+use jsonld::nodemap::{Pointer, Value};
+use serde_json::json;
+use serde_json::Value as JValue;
 use std::error::Error;
+use std::fmt;
 
-use kroeg_tap::{as2, Context, MessageHandler};
+use kroeg_tap::{
+    as2, assign_id, kroeg, litepub, ActivityHandler, Context, ErrorKind, StoreError, StoreItem,
+    TapError,
+};
+
+/// Reaction with an arbitrary emoji, as sent by e.g. Pleroma.
+pub const EMOJI_REACT: &'static str = litepub!(EmojiReact);
+
+/// Returns the emoji an `EmojiReact`, or a `Like` with content, reacts with.
+pub(crate) fn reaction_content(activity: &StoreItem) -> Option<String> {
+    if !activity
+        .main()
+        .types
+        .iter()
+        .any(|f| f == as2!(Like) || f == EMOJI_REACT)
+    {
+        return None;
+    }
+
+    match activity.main()[as2!(content)].first() {
+        Some(Pointer::Value(Value {
+            value: JValue::String(content),
+            ..
+        })) if !content.trim().is_empty() => Some(content.trim().to_owned()),
+        _ => None,
+    }
+}
+
+fn change_total(reaction: &mut StoreItem, change: i64) {
+    let total = match reaction.main()[as2!(totalItems)].first() {
+        Some(Pointer::Value(Value {
+            value: JValue::Number(num),
+            ..
+        })) => num.as_i64().unwrap_or(0),
+        _ => 0,
+    };
+
+    let values = reaction.main_mut().get_mut(as2!(totalItems));
+    values.clear();
+    values.push(Pointer::Value(Value {
+        value: (total + change).max(0).into(),
+        type_id: Some("http://www.w3.org/2001/XMLSchema#nonNegativeInteger".to_owned()),
+        language: None,
+    }));
+}
+
+/// Finds the group of reactions with a specific emoji on an object.
+async fn find_reaction(
+    context: &mut Context<'_, '_>,
+    object: &StoreItem,
+    emoji: &str,
+) -> Result<Option<StoreItem>, StoreError> {
+    for pointer in &object.main()[kroeg!(reactions)] {
+        let id = match pointer {
+            Pointer::Id(id) => id,
+            _ => continue,
+        };
+
+        if let Some(reaction) = context.entity_store.get(id.to_owned(), true).await? {
+            let matches = match reaction.main()[as2!(content)].first() {
+                Some(Pointer::Value(Value {
+                    value: JValue::String(content),
+                    ..
+                })) => content == emoji,
+                _ => false,
+            };
+
+            if matches {
+                return Ok(Some(reaction));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Records `activity` as a reaction with `emoji` on the object. Reactions are grouped
+///  per emoji, and each group keeps count of its reactions in `as:totalItems`.
+pub(crate) async fn add_reaction(
+    context: &mut Context<'_, '_>,
+    object: &mut StoreItem,
+    emoji: &str,
+    activity: &str,
+) -> Result<(), StoreError> {
+    let mut reaction = match find_reaction(context, object, emoji).await? {
+        Some(reaction) => reaction,
+        None => {
+            let id = assign_id(context, None, Some(object.id().to_owned()), 1).await?;
+            let mut reaction = StoreItem::parse(
+                &id,
+                &json!({
+                    "@id": id,
+                    "@type": [kroeg!(Reaction)],
+                    as2!(content): [{"@value": emoji}],
+                    as2!(partOf): [{"@id": object.id()}]
+                }),
+            )
+            .unwrap();
+
+            reaction.meta()[kroeg!(instance)].push(Pointer::Value(Value {
+                value: context.instance_id.into(),
+                type_id: Some("http://www.w3.org/2001/XMLSchema#integer".to_owned()),
+                language: None,
+            }));
+
+            object.main_mut()[kroeg!(reactions)].push(Pointer::Id(id));
+            context
+                .entity_store
+                .put(object.id().to_owned(), object)
+                .await?;
+
+            reaction
+        }
+    };
+
+    let existing = context
+        .entity_store
+        .find_collection(reaction.id().to_owned(), activity.to_owned())
+        .await?;
+    if !existing.items.is_empty() {
+        return Ok(());
+    }
+
+    context
+        .entity_store
+        .insert_collection(reaction.id().to_owned(), activity.to_owned())
+        .await?;
+
+    change_total(&mut reaction, 1);
+    context
+        .entity_store
+        .put(reaction.id().to_owned(), &mut reaction)
+        .await
+}
+
+/// Removes `activity` from the reactions with `emoji` on the object.
+pub(crate) async fn remove_reaction(
+    context: &mut Context<'_, '_>,
+    object: &StoreItem,
+    emoji: &str,
+    activity: &str,
+) -> Result<(), StoreError> {
+    let mut reaction = match find_reaction(context, object, emoji).await? {
+        Some(reaction) => reaction,
+        None => return Ok(()),
+    };
+
+    let existing = context
+        .entity_store
+        .find_collection(reaction.id().to_owned(), activity.to_owned())
+        .await?;
+    if existing.items.is_empty() {
+        return Ok(());
+    }
+
+    context
+        .entity_store
+        .remove_collection(reaction.id().to_owned(), activity.to_owned())
+        .await?;
+
+    change_total(&mut reaction, -1);
+    context
+        .entity_store
+        .put(reaction.id().to_owned(), &mut reaction)
+        .await
+}
+
+#[derive(Debug)]
+pub enum ServerLikeError {
+    MissingInbox,
+}
+
+impl fmt::Display for ServerLikeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerLikeError::MissingInbox => {
+                write!(f, "The inbox the activity was delivered to does not exist")
+            }
+        }
+    }
+}
+
+impl Error for ServerLikeError {}
+
+impl From<ServerLikeError> for TapError {
+    fn from(error: ServerLikeError) -> TapError {
+        TapError::new(ErrorKind::NotFound, error)
+    }
+}
 
 pub struct ServerLikeHandler;
 
 #[async_trait::async_trait]
-impl MessageHandler for ServerLikeHandler {
-    async fn handle(
+impl ActivityHandler for ServerLikeHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Like), as2!(Announce), EMOJI_REACT]
+    }
+
+    async fn handle_activity(
         &self,
         context: &mut Context<'_, '_>,
-        inbox: &mut String,
-        elem: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
-        let root = match context.entity_store.get(elem.to_owned(), false).await? {
-            Some(root) => root,
-            None => return Ok(()),
-        };
-
+        inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
         let is_like = root.main().types.iter().any(|f| f == as2!(Like));
         let is_announce = root.main().types.iter().any(|f| f == as2!(Announce));
 
-        if !is_like && !is_announce {
-            return Ok(());
-        }
+        let reaction = reaction_content(root);
 
         let inbox = context
             .entity_store
             .get(inbox.to_owned(), true)
             .await?
-            .unwrap();
+            .ok_or(ServerLikeError::MissingInbox)?;
         let attributed_to = &inbox.main()[as2!(attributedTo)];
 
         if attributed_to.is_empty() {
@@ -42,7 +230,7 @@ impl MessageHandler for ServerLikeHandler {
             } else {
                 continue;
             };
-            if let Some(object) = context.entity_store.get(id, false).await? {
+            if let Some(mut object) = context.entity_store.get(id, false).await? {
                 if !object.is_owned(&context) {
                     continue;
                 }
@@ -52,11 +240,14 @@ impl MessageHandler for ServerLikeHandler {
                     continue;
                 }
 
-                if is_like {
+                // Likes with content are emoji reactions, keep them apart from plain likes.
+                if let Some(emoji) = &reaction {
+                    add_reaction(context, &mut object, emoji, root.id()).await?;
+                } else if is_like {
                     if let [Pointer::Id(collection)] = &object.main()[as2!(likes)] as &[Pointer] {
                         context
                             .entity_store
-                            .insert_collection(collection.to_owned(), elem.to_owned())
+                            .insert_collection(collection.to_owned(), root.id().to_owned())
                             .await?;
                     }
                 }
@@ -65,7 +256,7 @@ impl MessageHandler for ServerLikeHandler {
                     if let [Pointer::Id(collection)] = &object.main()[as2!(shares)] as &[Pointer] {
                         context
                             .entity_store
-                            .insert_collection(collection.to_owned(), elem.to_owned())
+                            .insert_collection(collection.to_owned(), root.id().to_owned())
                             .await?;
                     }
                 }
@@ -78,15 +269,27 @@ impl MessageHandler for ServerLikeHandler {
 
 #[cfg(test)]
 mod test {
-    use super::ServerLikeHandler;
+    use super::{ServerLikeError, ServerLikeHandler};
     use crate::test::TestStore;
     use crate::{handle_object_pair, object_under_test};
     use async_std::task::block_on;
-    use kroeg_tap::{as2, MessageHandler};
+    use jsonld::nodemap::{Pointer, Value};
+    use kroeg_tap::{as2, kroeg, MessageHandler};
 
     fn setup() -> (TestStore, ()) {
+        let mut react = object_under_test!(remote "/like_e" => {
+            types => [as2!(Like)];
+            as2!(object) => ["/object_a"];
+        });
+        react.main_mut()[as2!(content)].push(Pointer::Value(Value {
+            value: "\u{1f44d}".into(),
+            type_id: None,
+            language: None,
+        }));
+
         (
             TestStore::new(vec![
+                react,
                 object_under_test!(remote "/like_a" => {
                     types => [as2!(Like)];
                     as2!(object) => ["/object_a"];
@@ -99,6 +302,10 @@ mod test {
                     types => [as2!(Announce)];
                     as2!(object) => ["/object_a"];
                 }),
+                object_under_test!(remote "/like_d" => {
+                    types => [super::EMOJI_REACT];
+                    as2!(object) => ["/object_a"];
+                }),
                 object_under_test!(local "/inbox" => {
                     types => [as2!(OrderedCollection)];
                     as2!(attributedTo) => ["/actor"];
@@ -191,4 +398,75 @@ mod test {
             Err(e) => panic!("Error: {}", e),
         }
     }
+
+    #[test]
+    fn groups_reactions() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerLikeHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/like_e".to_owned(),
+        )) {
+            Ok(()) => {
+                let reactions = &store.item("/object_a").unwrap().main()[kroeg!(reactions)];
+                let reaction = match reactions as &[Pointer] {
+                    [Pointer::Id(reaction)] => reaction.to_owned(),
+                    _ => panic!("Handler did not register the reaction"),
+                };
+
+                assert!(
+                    store.contains(&reaction, "/like_e"),
+                    "Handler did not record the reaction"
+                );
+                assert!(
+                    !store.contains("/object_a/likes", "/like_e"),
+                    "Handler registered reaction as like"
+                );
+            }
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn ignores_react_without_emoji() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerLikeHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/like_d".to_owned(),
+        )) {
+            Ok(()) => {
+                assert!(
+                    store.item("/object_a").unwrap().main()[kroeg!(reactions)].is_empty(),
+                    "Handler registered a reaction without emoji"
+                );
+            }
+            Err(e) => panic!("Error: {}", e),
+        }
+    }
+
+    #[test]
+    fn refuses_missing_inbox() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerLikeHandler.handle(
+            &mut context,
+            &mut "/missing".to_owned(),
+            &mut "/like_a".to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted an activity for a missing inbox"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    ServerLikeError::MissingInbox => { /* ok! */ }
+                },
+
+                Err(e) => panic!("handler refused activity: {}", e),
+            },
+        }
+    }
 }
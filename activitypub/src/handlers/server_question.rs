@@ -0,0 +1,553 @@
+use chrono::{DateTime, Utc};
+use jsonld::nodemap::{Entity, Pointer, Value};
+use serde_json::Value as JValue;
+use std::error::Error;
+use std::fmt;
+
+use kroeg_tap::{
+    as2, kroeg, toot, ActivityHandler, Context, ErrorKind, StoreError, StoreItem, TapError,
+};
+
+#[derive(Debug)]
+pub enum ServerQuestionError {
+    DuplicateVote,
+}
+
+impl fmt::Display for ServerQuestionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ServerQuestionError::DuplicateVote => {
+                write!(f, "The actor has already voted for this option")
+            }
+        }
+    }
+}
+
+impl Error for ServerQuestionError {}
+
+impl From<ServerQuestionError> for TapError {
+    fn from(error: ServerQuestionError) -> TapError {
+        TapError::new(ErrorKind::Conflict, error)
+    }
+}
+
+const VOTERS_COUNT: &'static str = toot!(votersCount);
+
+fn string_value(values: &[Pointer]) -> Option<&str> {
+    match values.first() {
+        Some(Pointer::Value(Value {
+            value: JValue::String(value),
+            ..
+        })) => Some(value),
+        _ => None,
+    }
+}
+
+fn count_value(count: u64) -> Pointer {
+    Pointer::Value(Value {
+        value: count.into(),
+        type_id: Some("http://www.w3.org/2001/XMLSchema#nonNegativeInteger".to_owned()),
+        language: None,
+    })
+}
+
+/// Increments the `as:totalItems` of a (reply) collection.
+fn increment_total(collection: &mut Entity) {
+    let total = match collection[as2!(totalItems)].first() {
+        Some(Pointer::Value(Value {
+            value: JValue::Number(num),
+            ..
+        })) => num.as_u64().unwrap_or(0),
+        Some(Pointer::Value(Value {
+            value: JValue::String(num),
+            ..
+        })) => num.parse().unwrap_or(0),
+        _ => 0,
+    };
+
+    let values = collection.get_mut(as2!(totalItems));
+    values.clear();
+    values.push(count_value(total + 1));
+}
+
+/// The value recording that `voter` voted for the option named `name`.
+fn vote_key(voter: &str, name: &str) -> Pointer {
+    Pointer::Value(Value {
+        value: format!("{} {}", voter, name).into(),
+        type_id: None,
+        language: None,
+    })
+}
+
+/// Returns true if the question is closed, or the end time of the question has passed.
+///  `as:closed` counts if it is either `true`, or a date and time that has passed.
+fn is_closed(question: &Entity) -> bool {
+    for closed in &question[as2!(closed)] {
+        match closed {
+            Pointer::Value(Value {
+                value: JValue::Bool(true),
+                ..
+            }) => return true,
+            Pointer::Value(Value {
+                value: JValue::String(closed),
+                ..
+            }) => match DateTime::parse_from_rfc3339(closed) {
+                Ok(closed) if closed < Utc::now() => return true,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    match string_value(&question[as2!(endTime)]).map(DateTime::parse_from_rfc3339) {
+        Some(Ok(end_time)) => end_time < Utc::now(),
+        _ => false,
+    }
+}
+
+/// Adds a vote to the option with ID `option` in `holder`, which is either the
+///  question itself, or the option if it is stored separately.
+async fn add_vote(
+    context: &mut Context<'_, '_>,
+    holder: &mut StoreItem,
+    option: &str,
+) -> Result<(), StoreError> {
+    let replies = match holder.sub(option) {
+        Some(option) => option[as2!(replies)].first().cloned(),
+        None => return Ok(()),
+    };
+
+    match replies {
+        Some(Pointer::Id(replies)) => {
+            if let Some(replies) = holder.sub_mut(&replies) {
+                increment_total(replies);
+            } else if let Some(mut item) = context.entity_store.get(replies, true).await? {
+                increment_total(item.main_mut());
+                context
+                    .entity_store
+                    .put(item.id().to_owned(), &mut item)
+                    .await?;
+            }
+        }
+
+        _ => {
+            let replies = holder.create();
+            replies.types.push(as2!(Collection).to_owned());
+            increment_total(replies);
+
+            let replies = replies.id.to_owned();
+            if let Some(option) = holder.sub_mut(option) {
+                option.get_mut(as2!(replies)).push(Pointer::Id(replies));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub struct ServerQuestionHandler;
+
+#[async_trait::async_trait]
+impl ActivityHandler for ServerQuestionHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
+        let inbox = match context.entity_store.get(inbox.to_owned(), true).await? {
+            Some(inbox) => inbox,
+            None => return Ok(()),
+        };
+        let attributed_to = &inbox.main()[as2!(attributedTo)];
+
+        if attributed_to.is_empty() {
+            return Ok(());
+        }
+
+        for pointer in &root.main()[as2!(object)] {
+            let id = match pointer {
+                Pointer::Id(id) => id,
+                _ => continue,
+            };
+
+            let vote = match context.entity_store.get(id.to_owned(), false).await? {
+                Some(vote) => vote,
+                None => continue,
+            };
+
+            let (name, voter) = match (
+                string_value(&vote.main()[as2!(name)]),
+                &vote.main()[as2!(attributedTo)] as &[Pointer],
+            ) {
+                (Some(name), [Pointer::Id(voter)]) => (name, voter.to_owned()),
+                _ => continue,
+            };
+
+            let question = match &vote.main()[as2!(inReplyTo)] as &[Pointer] {
+                [Pointer::Id(question)] => question.to_owned(),
+                _ => continue,
+            };
+
+            let mut question = match context.entity_store.get(question, true).await? {
+                Some(question) => question,
+                None => continue,
+            };
+
+            if !question.is_owned(context)
+                || !question.main().types.iter().any(|f| f == as2!(Question))
+            {
+                continue;
+            }
+
+            // Ensure votes only get processed iff the author of the question receives them.
+            if &question.main()[as2!(attributedTo)] != attributed_to {
+                continue;
+            }
+
+            // Votes that arrive too late are silently ignored.
+            if is_closed(question.main()) {
+                continue;
+            }
+
+            let multiple = !question.main()[as2!(anyOf)].is_empty();
+            let options = if multiple {
+                question.main()[as2!(anyOf)].clone()
+            } else {
+                question.main()[as2!(oneOf)].clone()
+            };
+
+            // Each option can be voted for once, and only a single one unless the
+            //  question allows multiple.
+            let key = vote_key(&voter, name);
+            let voter = Pointer::Id(voter);
+            let has_voted = question.meta()[kroeg!(voter)].contains(&voter);
+            if (has_voted && !multiple) || question.meta()[kroeg!(vote)].contains(&key) {
+                return Err(ServerQuestionError::DuplicateVote.into());
+            }
+
+            let mut matched = false;
+            for option in options {
+                let option = match option {
+                    Pointer::Id(option) => option,
+                    _ => continue,
+                };
+
+                // Options are usually embedded in the question, but may be stored separately.
+                if let Some(entity) = question.sub(&option) {
+                    if string_value(&entity[as2!(name)]) == Some(name) {
+                        add_vote(context, &mut question, &option).await?;
+                        matched = true;
+                        break;
+                    }
+                } else if let Some(mut item) = context.entity_store.get(option, true).await? {
+                    if string_value(&item.main()[as2!(name)]) == Some(name) {
+                        let option = item.id().to_owned();
+                        add_vote(context, &mut item, &option).await?;
+                        context.entity_store.put(option, &mut item).await?;
+                        matched = true;
+                        break;
+                    }
+                }
+            }
+
+            // Replies that don't name an option are not votes.
+            if !matched {
+                continue;
+            }
+
+            if !has_voted {
+                question.meta()[kroeg!(voter)].push(voter);
+            }
+
+            question.meta()[kroeg!(vote)].push(key);
+
+            let voters = question.meta()[kroeg!(voter)].len() as u64;
+            let voters_count = question.main_mut().get_mut(VOTERS_COUNT);
+            voters_count.clear();
+            voters_count.push(count_value(voters));
+
+            context
+                .entity_store
+                .put(question.id().to_owned(), &mut question)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_closed, ServerQuestionError, ServerQuestionHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use jsonld::nodemap::{Pointer, Value};
+    use kroeg_tap::{as2, kroeg, MessageHandler, StoreItem};
+    use serde_json::{json, Value as JValue};
+
+    fn question(id: &str, end_time: &str) -> StoreItem {
+        poll(id, end_time, as2!(oneOf))
+    }
+
+    fn poll(id: &str, end_time: &str, options: &str) -> StoreItem {
+        let mut item = StoreItem::parse(
+            id,
+            &json!({
+                "@id": id,
+                "@type": [as2!(Question)],
+                as2!(attributedTo): [{"@id": "/actor"}],
+                as2!(endTime): [{"@value": end_time}],
+                options: [
+                    {"@type": [as2!(Note)], as2!(name): [{"@value": "yes"}]},
+                    {"@type": [as2!(Note)], as2!(name): [{"@value": "no"}]}
+                ]
+            }),
+        )
+        .unwrap();
+
+        item.meta()
+            .get_mut(kroeg!(instance))
+            .push(Pointer::Value(Value {
+                value: 1.into(),
+                type_id: Some("http://www.w3.org/2001/XMLSchema#integer".to_owned()),
+                language: None,
+            }));
+
+        item
+    }
+
+    fn vote(id: &str, question: &str, name: &str) -> StoreItem {
+        StoreItem::parse(
+            id,
+            &json!({
+                "@id": id,
+                "@type": [as2!(Note)],
+                as2!(attributedTo): [{"@id": "/voter"}],
+                as2!(inReplyTo): [{"@id": question}],
+                as2!(name): [{"@value": name}]
+            }),
+        )
+        .unwrap()
+    }
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![
+                object_under_test!(local "/inbox" => {
+                    types => [as2!(OrderedCollection)];
+                    as2!(attributedTo) => ["/actor"];
+                }),
+                question("/question", "2999-01-01T00:00:00Z"),
+                question("/closed", "2000-01-01T00:00:00Z"),
+                poll("/multiple", "2999-01-01T00:00:00Z", as2!(anyOf)),
+                vote("/any", "/multiple", "yes"),
+                vote("/again", "/multiple", "yes"),
+                vote("/other", "/multiple", "no"),
+                vote("/vote", "/question", "yes"),
+                vote("/late", "/closed", "yes"),
+                vote("/reply", "/question", "maybe"),
+                object_under_test!(remote "/vote/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/voter"];
+                    as2!(object) => ["/vote"];
+                }),
+                object_under_test!(remote "/late/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/voter"];
+                    as2!(object) => ["/late"];
+                }),
+                object_under_test!(remote "/any/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/voter"];
+                    as2!(object) => ["/any"];
+                }),
+                object_under_test!(remote "/again/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/voter"];
+                    as2!(object) => ["/again"];
+                }),
+                object_under_test!(remote "/other/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/voter"];
+                    as2!(object) => ["/other"];
+                }),
+                object_under_test!(remote "/reply/create" => {
+                    types => [as2!(Create)];
+                    as2!(actor) => ["/voter"];
+                    as2!(object) => ["/reply"];
+                }),
+            ]),
+            (),
+        )
+    }
+
+    fn tally(store: &TestStore, question: &str, name: &str) -> u64 {
+        let question = store.item(question).expect("question disappeared");
+
+        let options = question.main()[as2!(oneOf)]
+            .iter()
+            .chain(&question.main()[as2!(anyOf)]);
+        for option in options {
+            let option = match option {
+                Pointer::Id(id) => question.sub(id).unwrap(),
+                _ => continue,
+            };
+
+            if option[as2!(name)]
+                != [Pointer::Value(Value {
+                    value: JValue::String(name.to_owned()),
+                    type_id: None,
+                    language: None,
+                })]
+            {
+                continue;
+            }
+
+            return match option[as2!(replies)].first() {
+                Some(Pointer::Id(replies)) => {
+                    match question.sub(replies).unwrap()[as2!(totalItems)].first() {
+                        Some(Pointer::Value(val)) => val.value.as_u64().unwrap(),
+                        _ => 0,
+                    }
+                }
+                _ => 0,
+            };
+        }
+
+        panic!("option {} does not exist", name);
+    }
+
+    #[test]
+    fn counts_vote() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerQuestionHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/vote/create".to_owned(),
+        )) {
+            Ok(()) => {
+                assert_eq!(tally(&store, "/question", "yes"), 1, "Vote not counted");
+                assert_eq!(tally(&store, "/question", "no"), 0, "Wrong option counted");
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn rejects_duplicate_vote() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        block_on(ServerQuestionHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/vote/create".to_owned(),
+        ))
+        .expect("first vote failed");
+
+        match block_on(ServerQuestionHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/vote/create".to_owned(),
+        )) {
+            Ok(()) => panic!("handler accepted duplicate vote"),
+            Err(e) => match e.downcast() {
+                Ok(val) => match *val {
+                    ServerQuestionError::DuplicateVote => { /* ok! */ }
+                },
+
+                Err(e) => panic!("handler refused vote: {}", e),
+            },
+        }
+
+        assert_eq!(tally(&store, "/question", "yes"), 1, "Vote counted twice");
+    }
+
+    #[test]
+    fn ignores_late_vote() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(ServerQuestionHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/late/create".to_owned(),
+        )) {
+            Ok(()) => assert_eq!(tally(&store, "/closed", "yes"), 0, "Late vote counted"),
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn counts_vote_after_unmatched_reply() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        for id in &["/reply/create", "/vote/create"] {
+            block_on(ServerQuestionHandler.handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut id.to_string(),
+            ))
+            .expect("handler refused reply");
+        }
+
+        assert_eq!(tally(&store, "/question", "yes"), 1, "Vote not counted");
+    }
+
+    #[test]
+    fn checks_closed_value() {
+        let closed = |value: JValue| {
+            let mut item = question("/question", "2999-01-01T00:00:00Z");
+            item.main_mut()[as2!(closed)].push(Pointer::Value(Value {
+                value,
+                type_id: None,
+                language: None,
+            }));
+
+            is_closed(item.main())
+        };
+
+        assert!(closed(json!(true)));
+        assert!(closed(json!("2000-01-01T00:00:00Z")));
+        assert!(!closed(json!(false)));
+        assert!(!closed(json!("2999-01-01T00:00:00Z")));
+        assert!(!closed(json!("yes")));
+    }
+
+    #[test]
+    fn counts_each_option_once() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        for id in &["/any/create", "/again/create", "/other/create"] {
+            let result = block_on(ServerQuestionHandler.handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut id.to_string(),
+            ));
+
+            match (*id, result) {
+                ("/again/create", Ok(())) => panic!("handler accepted repeated vote"),
+                ("/again/create", Err(_)) => {}
+                (_, Err(e)) => panic!("handler refused vote: {}", e),
+                (_, Ok(())) => {}
+            }
+        }
+
+        assert_eq!(tally(&store, "/multiple", "yes"), 1, "Vote counted twice");
+        assert_eq!(
+            tally(&store, "/multiple", "no"),
+            1,
+            "Other option not counted"
+        );
+    }
+}
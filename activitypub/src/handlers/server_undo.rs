@@ -0,0 +1,206 @@
+use jsonld::nodemap::Pointer;
+
+use kroeg_tap::{as2, ActivityHandler, Context, StoreItem, TapError};
+
+use super::server_like::{reaction_content, remove_reaction};
+
+pub struct ServerUndoHandler;
+
+#[async_trait::async_trait]
+impl ActivityHandler for ServerUndoHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Undo)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
+        let undone = match &root.main()[as2!(object)] as &[Pointer] {
+            [Pointer::Id(undone)] => undone.to_owned(),
+            _ => return Ok(()),
+        };
+
+        let undone = match context.entity_store.get(undone, false).await? {
+            Some(undone) => undone,
+            None => return Ok(()),
+        };
+
+        // Only the actor that sent the activity can undo it.
+        if root.main()[as2!(actor)].is_empty()
+            || &root.main()[as2!(actor)] != &undone.main()[as2!(actor)]
+        {
+            return Ok(());
+        }
+
+        let is_like = undone.main().types.iter().any(|f| f == as2!(Like));
+        let is_announce = undone.main().types.iter().any(|f| f == as2!(Announce));
+        let reaction = reaction_content(&undone);
+
+        if !is_like && !is_announce && reaction.is_none() {
+            return Ok(());
+        }
+
+        let inbox = match context.entity_store.get(inbox.to_owned(), true).await? {
+            Some(inbox) => inbox,
+            None => return Ok(()),
+        };
+        let attributed_to = &inbox.main()[as2!(attributedTo)];
+
+        if attributed_to.is_empty() {
+            return Ok(());
+        }
+
+        for pointer in &undone.main()[as2!(object)] {
+            let id = match pointer {
+                Pointer::Id(id) => id,
+                _ => continue,
+            };
+
+            let object = match context.entity_store.get(id.to_owned(), false).await? {
+                Some(object) => object,
+                None => continue,
+            };
+
+            if !object.is_owned(context) || &object.main()[as2!(attributedTo)] != attributed_to {
+                continue;
+            }
+
+            if let Some(emoji) = &reaction {
+                remove_reaction(context, &object, emoji, undone.id()).await?;
+            } else if is_like {
+                if let [Pointer::Id(likes)] = &object.main()[as2!(likes)] as &[Pointer] {
+                    context
+                        .entity_store
+                        .remove_collection(likes.to_owned(), undone.id().to_owned())
+                        .await?;
+                }
+            }
+
+            if is_announce {
+                if let [Pointer::Id(shares)] = &object.main()[as2!(shares)] as &[Pointer] {
+                    context
+                        .entity_store
+                        .remove_collection(shares.to_owned(), undone.id().to_owned())
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ServerUndoHandler;
+    use crate::handlers::ServerLikeHandler;
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use jsonld::nodemap::{Pointer, Value};
+    use kroeg_tap::{as2, kroeg, MessageHandler};
+
+    fn setup() -> (TestStore, ()) {
+        let mut react = object_under_test!(remote "/react" => {
+            types => [crate::handlers::EMOJI_REACT];
+            as2!(actor) => ["/remote"];
+            as2!(object) => ["/object"];
+        });
+        react.main_mut()[as2!(content)].push(Pointer::Value(Value {
+            value: "\u{2b50}".into(),
+            type_id: None,
+            language: None,
+        }));
+
+        (
+            TestStore::new(vec![
+                react,
+                object_under_test!(remote "/undo" => {
+                    types => [as2!(Undo)];
+                    as2!(actor) => ["/remote"];
+                    as2!(object) => ["/react"];
+                }),
+                object_under_test!(remote "/undo/spoofed" => {
+                    types => [as2!(Undo)];
+                    as2!(actor) => ["/other"];
+                    as2!(object) => ["/react"];
+                }),
+                object_under_test!(local "/inbox" => {
+                    types => [as2!(OrderedCollection)];
+                    as2!(attributedTo) => ["/actor"];
+                }),
+                object_under_test!(local "/object" => {
+                    types => [as2!(Note)];
+                    as2!(attributedTo) => ["/actor"];
+                }),
+            ]),
+            (),
+        )
+    }
+
+    fn reaction(store: &TestStore) -> String {
+        match &store.item("/object").unwrap().main()[kroeg!(reactions)] as &[Pointer] {
+            [Pointer::Id(reaction)] => reaction.to_owned(),
+            _ => panic!("reaction was not registered"),
+        }
+    }
+
+    #[test]
+    fn removes_reaction() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        block_on(ServerLikeHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/react".to_owned(),
+        ))
+        .expect("failed to react");
+
+        match block_on(ServerUndoHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/undo".to_owned(),
+        )) {
+            Ok(()) => {
+                let reaction = reaction(&store);
+                assert!(
+                    !store.contains(&reaction, "/react"),
+                    "Handler did not remove the reaction"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn ignores_other_actor() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        block_on(ServerLikeHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/react".to_owned(),
+        ))
+        .expect("failed to react");
+
+        match block_on(ServerUndoHandler.handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/undo/spoofed".to_owned(),
+        )) {
+            Ok(()) => {
+                let reaction = reaction(&store);
+                assert!(
+                    store.contains(&reaction, "/react"),
+                    "Handler removed a reaction of another actor"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use jsonld::nodemap::Pointer;
+
+use kroeg_tap::{as2, ActivityHandler, Context, StoreItem, TapError, Visibility};
+
+use super::create_actor::ensure_collection;
+
+/// The ID of the collection containing all public posts by local actors.
+pub fn local_timeline(context: &Context) -> String {
+    format!("{}/timeline/local", context.server_base)
+}
+
+/// The ID of the collection containing all public posts received by this server.
+pub fn federated_timeline(context: &Context) -> String {
+    format!("{}/timeline/federated", context.server_base)
+}
+
+/// Adds `Create`s of public objects to the local (when posted to an outbox)
+///  or the federated (when received in an inbox) timeline.
+pub struct PublicTimelineHandler(pub bool);
+
+#[async_trait::async_trait]
+impl ActivityHandler for PublicTimelineHandler {
+    fn applies_to(&self) -> &[&str] {
+        &[as2!(Create)]
+    }
+
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        _inbox: &str,
+        root: &StoreItem,
+    ) -> Result<(), TapError> {
+        // Unlisted, followers-only and direct posts never end up in the timelines.
+        //  The addressing of the Create itself may differ from the object it wraps.
+        let objects = &root.main()[as2!(object)];
+        if objects.is_empty() {
+            return Ok(());
+        }
+
+        for pointer in objects {
+            let object = match pointer {
+                Pointer::Id(id) => context.entity_store.get(id.to_owned(), false).await?,
+                _ => None,
+            };
+
+            match object {
+                Some(object) if Visibility::of(object.main(), &[]) == Visibility::Public => {}
+                _ => return Ok(()),
+            }
+        }
+
+        let local_post = self.0;
+        if local_post && !root.is_owned(context) {
+            return Ok(());
+        }
+
+        let timeline = if local_post {
+            local_timeline(context)
+        } else {
+            federated_timeline(context)
+        };
+
+        let server_base = context.server_base.to_owned();
+        ensure_collection(context, &timeline, &server_base).await?;
+
+        // The same activity is received once for every local recipient.
+        let existing = context
+            .entity_store
+            .find_collection(timeline.to_owned(), root.id().to_owned())
+            .await?;
+
+        if existing.items.is_empty() {
+            context
+                .entity_store
+                .insert_collection(timeline, root.id().to_owned())
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PublicTimelineHandler;
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{as2, MessageHandler};
+
+    fn setup() -> (TestStore, ()) {
+        (
+            TestStore::new(vec![
+                object_under_test!(local "/local/create" => {
+                    types => [as2!(Create)];
+                    as2!(to) => [as2!(Public)];
+                    as2!(object) => ["/local/object"];
+                }),
+                object_under_test!(remote "/remote/create" => {
+                    types => [as2!(Create)];
+                    as2!(to) => [as2!(Public)];
+                    as2!(object) => ["/remote/object"];
+                }),
+                object_under_test!(remote "/remote/unlisted" => {
+                    types => [as2!(Create)];
+                    as2!(to) => ["/remote/followers"];
+                    as2!(cc) => [as2!(Public)];
+                    as2!(object) => ["/remote/unlisted/object"];
+                }),
+                object_under_test!(remote "/remote/direct" => {
+                    types => [as2!(Create)];
+                    as2!(to) => ["/subject"];
+                    as2!(object) => ["/remote/direct/object"];
+                }),
+                object_under_test!(remote "/remote/followers" => {
+                    types => [as2!(Create)];
+                    as2!(to) => [as2!(Public)];
+                    as2!(object) => ["/remote/followers/object"];
+                }),
+                object_under_test!(local "/local/object" => {
+                    types => [as2!(Note)];
+                    as2!(to) => [as2!(Public)];
+                }),
+                object_under_test!(remote "/remote/object" => {
+                    types => [as2!(Note)];
+                    as2!(to) => [as2!(Public)];
+                }),
+                object_under_test!(remote "/remote/unlisted/object" => {
+                    types => [as2!(Note)];
+                    as2!(to) => ["/remote/followers"];
+                    as2!(cc) => [as2!(Public)];
+                }),
+                object_under_test!(remote "/remote/direct/object" => {
+                    types => [as2!(Note)];
+                    as2!(to) => ["/subject"];
+                }),
+                object_under_test!(remote "/remote/followers/object" => {
+                    types => [as2!(Note)];
+                    as2!(to) => ["/remote/actor/followers"];
+                }),
+            ]),
+            (),
+        )
+    }
+
+    #[test]
+    fn adds_local_post() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(PublicTimelineHandler(true).handle(
+            &mut context,
+            &mut "/outbox".to_owned(),
+            &mut "/local/create".to_owned(),
+        )) {
+            Ok(()) => {
+                assert!(
+                    store.contains("/timeline/local", "/local/create"),
+                    "Handler did not add the post to the local timeline"
+                );
+                assert!(
+                    !store.contains("/timeline/federated", "/local/create"),
+                    "Handler added the post to the federated timeline"
+                );
+            }
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn adds_received_post() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        match block_on(PublicTimelineHandler(false).handle(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/remote/create".to_owned(),
+        )) {
+            Ok(()) => assert!(
+                store.contains("/timeline/federated", "/remote/create"),
+                "Handler did not add the post to the federated timeline"
+            ),
+            Err(e) => panic!("handler returned error: {}", e),
+        }
+    }
+
+    #[test]
+    fn ignores_non_public_posts() {
+        let (mut store, mut queue) = setup();
+        let mut context = store.context(&mut queue);
+
+        for post in &["/remote/unlisted", "/remote/direct", "/remote/followers"] {
+            block_on(PublicTimelineHandler(false).handle(
+                &mut context,
+                &mut "/inbox".to_owned(),
+                &mut post.to_string(),
+            ))
+            .expect("handler returned error");
+        }
+
+        assert!(
+            !store.contains("/timeline/federated", "/remote/unlisted"),
+            "Handler added an unlisted post to the federated timeline"
+        );
+        assert!(
+            !store.contains("/timeline/federated", "/remote/direct"),
+            "Handler added a direct message to the federated timeline"
+        );
+        assert!(
+            !store.contains("/timeline/federated", "/remote/followers"),
+            "Handler added a public Create of a followers-only post to the federated timeline"
+        );
+    }
+}
@@ -3,7 +3,7 @@ use std::error::Error;
 use std::fmt;
 use url::Url;
 
-use kroeg_tap::{as2, Context, MessageHandler};
+use kroeg_tap::{as2, Context, ErrorKind, MessageHandler, TapError};
 
 #[derive(Debug)]
 pub enum RequiredEventsError {
@@ -44,6 +44,21 @@ impl fmt::Display for RequiredEventsError {
 
 impl Error for RequiredEventsError {}
 
+impl From<RequiredEventsError> for TapError {
+    fn from(error: RequiredEventsError) -> TapError {
+        let kind = match error {
+            RequiredEventsError::FailedToRetrieve => ErrorKind::Remote,
+            RequiredEventsError::MissingObject => ErrorKind::InvalidInput,
+            RequiredEventsError::MissingActor => ErrorKind::InvalidInput,
+            RequiredEventsError::MayNotPublish => ErrorKind::Unauthorized,
+            RequiredEventsError::NotAllowedtoAct => ErrorKind::Unauthorized,
+            RequiredEventsError::ActorAttributedToDoNotMatch => ErrorKind::Unauthorized,
+        };
+
+        TapError::new(kind, error)
+    }
+}
+
 pub struct VerifyRequiredEventsHandler(pub bool);
 
 fn same_origin(a: &str, b: &str) -> bool {
@@ -65,7 +80,7 @@ impl MessageHandler for VerifyRequiredEventsHandler {
         context: &mut Context<'_, '_>,
         _inbox: &mut String,
         id: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>> {
+    ) -> Result<(), TapError> {
         let val = context
             .entity_store
             .get(id.to_owned(), false)
@@ -141,7 +156,7 @@ mod test {
     use crate::test::TestStore;
     use crate::{handle_object_pair, object_under_test};
     use async_std::task::block_on;
-    use kroeg_tap::{as2, MessageHandler};
+    use kroeg_tap::{as2, ErrorKind, MessageHandler};
 
     fn setup() -> (TestStore, ()) {
         (
@@ -232,14 +247,18 @@ mod test {
             &mut "/a".to_owned(),
         )) {
             Ok(()) => panic!("handler accepted object"),
-            Err(e) => match e.downcast() {
-                Ok(val) => match *val {
-                    RequiredEventsError::ActorAttributedToDoNotMatch => { /* ok! */ }
-                    e => panic!("handler refused object for wrong reason: {}", e),
-                },
+            Err(e) => {
+                assert_eq!(e.kind(), ErrorKind::Unauthorized);
 
-                Err(e) => panic!("handler refused object: {}", e),
-            },
+                match e.downcast() {
+                    Ok(val) => match *val {
+                        RequiredEventsError::ActorAttributedToDoNotMatch => { /* ok! */ }
+                        e => panic!("handler refused object for wrong reason: {}", e),
+                    },
+
+                    Err(e) => panic!("handler refused object: {}", e),
+                }
+            }
         }
     }
 
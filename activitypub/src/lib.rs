@@ -1,6 +1,7 @@
 #![feature(never_type)]
 
 pub mod handlers;
+pub mod pipeline;
 
 #[macro_use]
 pub mod test;
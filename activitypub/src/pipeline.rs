@@ -0,0 +1,458 @@
+//! The default handler pipelines for outboxes and inboxes.
+//!
+//! Both pipelines consist of the following stages:
+//!  - `prepare`: normalizes or filters the incoming message
+//!  - `verify`: checks that the message is allowed
+//!  - `process`: applies the side effects of the activity
+//!  - `index`: adds the activity to timelines and other collections
+
+use kroeg_tap::{Handler, Pipeline};
+
+use crate::handlers::*;
+
+/// The pipeline for messages posted to an outbox by a local actor.
+pub fn outbox_default() -> Pipeline {
+    Pipeline::new()
+        .with_stage("prepare", vec![Handler::message(AutomaticCreateHandler)])
+        .with_stage(
+            "verify",
+            vec![Handler::message(VerifyRequiredEventsHandler(true))],
+        )
+        .with_stage(
+            "process",
+            vec![
+                Handler::message(CreateActorHandler),
+                Handler::activity(ClientCreateHandler),
+                Handler::activity(ClientLikeHandler),
+                Handler::activity(ClientAddRemoveHandler),
+                Handler::activity(ClientUndoHandler),
+            ],
+        )
+        .with_stage(
+            "index",
+            vec![
+                Handler::activity(ConversationHandler),
+                Handler::activity(HashtagHandler),
+                Handler::activity(PublicTimelineHandler(true)),
+                Handler::activity(HomeTimelineHandler(true)),
+            ],
+        )
+}
+
+/// The pipeline for messages delivered to the inbox of a local actor.
+pub fn inbox_default() -> Pipeline {
+    Pipeline::new()
+        .with_stage("prepare", vec![Handler::activity(DeduplicateHandler)])
+        .with_stage(
+            "verify",
+            vec![Handler::message(VerifyRequiredEventsHandler(false))],
+        )
+        .with_stage(
+            "process",
+            vec![
+                Handler::activity(ServerCreateHandler),
+                Handler::activity(ServerLikeHandler),
+                Handler::activity(ServerUndoHandler),
+                Handler::activity(ServerQuestionHandler),
+                Handler::activity(ServerFollowHandler),
+            ],
+        )
+        .with_stage(
+            "index",
+            vec![
+                Handler::activity(ConversationHandler),
+                Handler::activity(HashtagHandler),
+                Handler::activity(PublicTimelineHandler(false)),
+                Handler::activity(HomeTimelineHandler(false)),
+            ],
+        )
+}
+
+#[cfg(test)]
+mod test {
+    use super::{inbox_default, outbox_default};
+    use crate::handlers::{PublicTimelineHandler, VerifyRequiredEventsHandler};
+    use crate::test::TestStore;
+    use crate::{handle_object_pair, object_under_test};
+    use async_std::task::block_on;
+    use kroeg_tap::{
+        as2, Change, Context, Handler, MessageHandler, Pipeline, QueueItem, QueueStore, StoreError,
+        TapError,
+    };
+
+    /// A queue that keeps everything added to it, and the IDs of the items
+    ///  marked as done.
+    #[derive(Debug, Default)]
+    struct TestQueue {
+        added: Vec<(String, String)>,
+        marked: Vec<u64>,
+    }
+
+    #[async_trait::async_trait]
+    impl QueueStore for TestQueue {
+        async fn get_item(&mut self) -> Result<Option<QueueItem>, StoreError> {
+            Ok(None)
+        }
+
+        async fn mark_success(&mut self, item: QueueItem) -> Result<(), StoreError> {
+            self.marked.push(item.id);
+
+            Ok(())
+        }
+
+        async fn mark_failure(&mut self, item: QueueItem) -> Result<(), StoreError> {
+            self.marked.push(item.id);
+
+            Ok(())
+        }
+
+        async fn add(&mut self, event: String, data: String) -> Result<(), StoreError> {
+            self.added.push((event, data));
+
+            Ok(())
+        }
+    }
+
+    /// Marks a queue item as delivered.
+    struct MarkDelivered;
+
+    #[async_trait::async_trait]
+    impl MessageHandler for MarkDelivered {
+        async fn handle(
+            &self,
+            context: &mut Context<'_, '_>,
+            _inbox: &mut String,
+            id: &mut String,
+        ) -> Result<(), TapError> {
+            let item = QueueItem {
+                id: 1,
+                event: "deliver".to_owned(),
+                data: id.to_owned(),
+            };
+
+            context.queue_store.mark_success(item).await
+        }
+    }
+
+    /// Indexes the message in two collections, and queues it for delivery.
+    struct IndexAndDeliver;
+
+    #[async_trait::async_trait]
+    impl MessageHandler for IndexAndDeliver {
+        async fn handle(
+            &self,
+            context: &mut Context<'_, '_>,
+            _inbox: &mut String,
+            id: &mut String,
+        ) -> Result<(), TapError> {
+            for collection in &["/first", "/second"] {
+                context
+                    .entity_store
+                    .insert_collection(collection.to_string(), id.to_owned())
+                    .await?;
+            }
+
+            context
+                .queue_store
+                .add("deliver".to_owned(), id.to_owned())
+                .await
+        }
+    }
+
+    #[test]
+    fn has_stages_in_order() {
+        for pipeline in &[outbox_default(), inbox_default()] {
+            let names: Vec<_> = pipeline.stages().iter().map(|f| f.name()).collect();
+            assert_eq!(names, vec!["prepare", "verify", "process", "index"]);
+        }
+    }
+
+    #[test]
+    fn routes_by_type() {
+        let mut store = TestStore::new(vec![
+            object_under_test!(remote "/create" => {
+                types => [as2!(Create)];
+                as2!(to) => [as2!(Public)];
+                as2!(object) => ["/object"];
+            }),
+            object_under_test!(remote "/like" => {
+                types => [as2!(Like)];
+                as2!(to) => [as2!(Public)];
+                as2!(object) => ["/object"];
+            }),
+            object_under_test!(remote "/object" => {
+                types => [as2!(Note)];
+                as2!(to) => [as2!(Public)];
+            }),
+        ]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        let pipeline = Pipeline::new().with_stage(
+            "index",
+            vec![Handler::activity(PublicTimelineHandler(false))],
+        );
+
+        for id in &["/create", "/like"] {
+            block_on(pipeline.run(&mut context, &mut "/inbox".to_owned(), &mut id.to_string()))
+                .expect("pipeline returned error");
+        }
+
+        assert!(
+            store.contains("/timeline/federated", "/create"),
+            "Pipeline did not pass the Create to the handler"
+        );
+        assert!(
+            !store.contains("/timeline/federated", "/like"),
+            "Pipeline passed the Like to a handler that doesn't apply to it"
+        );
+    }
+
+    #[test]
+    fn rolls_back_on_error() {
+        let mut store = TestStore::new(vec![
+            object_under_test!(remote "/create" => {
+                types => [as2!(Create)];
+                as2!(to) => [as2!(Public)];
+                as2!(object) => ["/object"];
+            }),
+            object_under_test!(remote "/object" => {
+                types => [as2!(Note)];
+                as2!(to) => [as2!(Public)];
+            }),
+        ]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        // The activity is indexed first, after which it turns out to be missing an actor.
+        let pipeline = Pipeline::new()
+            .with_stage(
+                "index",
+                vec![Handler::activity(PublicTimelineHandler(false))],
+            )
+            .with_stage(
+                "verify",
+                vec![Handler::message(VerifyRequiredEventsHandler(false))],
+            );
+
+        if let Ok(()) = block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        )) {
+            panic!("pipeline accepted activity without actor");
+        }
+
+        assert!(
+            store.item("/timeline/federated").is_none(),
+            "Pipeline kept the collection created by the handler"
+        );
+        assert!(
+            !store.contains("/timeline/federated", "/create"),
+            "Pipeline kept the changes of the handler"
+        );
+    }
+
+    #[test]
+    fn dry_run_reports_changes() {
+        let mut store = TestStore::new(vec![
+            object_under_test!(remote "/create" => {
+                types => [as2!(Create)];
+                as2!(to) => [as2!(Public)];
+                as2!(object) => ["/object"];
+            }),
+            object_under_test!(remote "/object" => {
+                types => [as2!(Note)];
+                as2!(to) => [as2!(Public)];
+            }),
+        ]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+
+        let pipeline = Pipeline::new().with_stage(
+            "index",
+            vec![Handler::activity(PublicTimelineHandler(false))],
+        );
+
+        let report = block_on(pipeline.dry_run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("pipeline returned error");
+
+        assert!(
+            report.changes.iter().any(|f| match f {
+                Change::InsertCollection(collection, item) =>
+                    collection == "/timeline/federated" && item == "/create",
+                _ => false,
+            }),
+            "Dry run did not report the insertion"
+        );
+        assert!(
+            !store.contains("/timeline/federated", "/create"),
+            "Dry run changed the store"
+        );
+    }
+
+    #[test]
+    fn stops_at_redelivery() {
+        let mut store = TestStore::new(vec![
+            object_under_test!(remote "/inbox" => {
+                types => [as2!(OrderedCollection)];
+            }),
+            object_under_test!(remote "/create" => {
+                types => [as2!(Create)];
+                as2!(actor) => ["/subject"];
+                as2!(to) => [as2!(Public)];
+                as2!(object) => ["/note"];
+            }),
+            object_under_test!(remote "/note" => {
+                types => [as2!(Note)];
+                as2!(attributedTo) => ["/subject"];
+                as2!(to) => [as2!(Public)];
+            }),
+        ]);
+        let mut queue = ();
+        let mut context = store.context(&mut queue);
+        let pipeline = inbox_default();
+
+        block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("pipeline refused first delivery");
+
+        block_on(
+            context
+                .entity_store
+                .remove_collection("/timeline/federated".to_owned(), "/create".to_owned()),
+        )
+        .unwrap();
+
+        block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("pipeline refused repeated delivery");
+
+        assert!(
+            !store.contains("/timeline/federated", "/create"),
+            "Pipeline ran the remaining stages on a repeated delivery"
+        );
+    }
+
+    #[test]
+    fn keeps_earlier_writes_if_store_fails() {
+        let mut store = TestStore::failing_after(vec![], 1);
+        let mut queue = TestQueue::default();
+        let mut context = store.context(&mut queue);
+
+        let pipeline = Pipeline::new().with_stage("index", vec![Handler::message(IndexAndDeliver)]);
+
+        if let Ok(()) = block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        )) {
+            panic!("pipeline ignored the failing store");
+        }
+
+        // As documented on `Pipeline::run`, writes before the failure are kept.
+        assert!(store.contains("/first", "/create"));
+        assert!(!store.contains("/second", "/create"));
+        assert!(
+            queue.added.is_empty(),
+            "Pipeline delivered after the store failed"
+        );
+    }
+
+    #[test]
+    fn dry_run_keeps_queue() {
+        let mut store = TestStore::new(vec![]);
+        let mut queue = TestQueue::default();
+        let mut context = store.context(&mut queue);
+
+        let pipeline = Pipeline::new().with_stage(
+            "index",
+            vec![
+                Handler::message(IndexAndDeliver),
+                Handler::message(MarkDelivered),
+            ],
+        );
+
+        let report = block_on(pipeline.dry_run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("pipeline returned error");
+        assert_eq!(report.queued.len(), 1);
+        assert!(queue.added.is_empty(), "Dry run added to the queue");
+        assert!(queue.marked.is_empty(), "Dry run marked a queue item");
+
+        let mut context = store.context(&mut queue);
+        block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("pipeline returned error");
+        assert_eq!(queue.marked, vec![1]);
+    }
+
+    #[test]
+    fn runs_nested_pipeline_in_one_transaction() {
+        let mut store = TestStore::transactional(vec![]);
+        let mut queue = TestQueue::default();
+        let mut context = store.context(&mut queue);
+
+        let nested = Pipeline::new().with_stage("index", vec![Handler::message(IndexAndDeliver)]);
+        let pipeline = Pipeline::new().with_stage("nested", vec![Handler::message(nested)]);
+
+        block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        ))
+        .expect("pipeline returned error");
+
+        assert_eq!(store.transactions(), 1);
+        assert!(store.contains("/second", "/create"));
+        assert_eq!(queue.added.len(), 1, "Nested pipeline did not deliver");
+    }
+
+    #[test]
+    fn discards_nested_pipeline_changes_on_error() {
+        let mut store = TestStore::new(vec![object_under_test!(remote "/create" => {
+            types => [as2!(Create)];
+            as2!(object) => ["/object"];
+        })]);
+        let mut queue = TestQueue::default();
+        let mut context = store.context(&mut queue);
+
+        let nested = Pipeline::new().with_stage("index", vec![Handler::message(IndexAndDeliver)]);
+        let pipeline = Pipeline::new()
+            .with_stage("nested", vec![Handler::message(nested)])
+            .with_stage(
+                "verify",
+                vec![Handler::message(VerifyRequiredEventsHandler(false))],
+            );
+
+        if let Ok(()) = block_on(pipeline.run(
+            &mut context,
+            &mut "/inbox".to_owned(),
+            &mut "/create".to_owned(),
+        )) {
+            panic!("pipeline accepted activity without actor");
+        }
+
+        assert!(
+            !store.contains("/first", "/create"),
+            "Pipeline kept the changes of the nested pipeline"
+        );
+        assert!(queue.added.is_empty(), "Pipeline kept the nested delivery");
+    }
+}
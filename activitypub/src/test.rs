@@ -1,13 +1,21 @@
+use jsonld::nodemap::Entity;
 use kroeg_tap::{
-    CollectionPointer, Context, EntityStore, QuadQuery, QueueStore, StoreError, StoreItem, User,
+    evaluate_query, item_quads, CollectionPointer, Context, EntityStore, HierarchicalIds,
+    QuadQuery, Query, QueueStore, StoreError, StoreItem, User,
 };
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct TestStore {
     data: HashMap<String, StoreItem>,
     items: HashMap<String, Vec<String>>,
     reads: HashSet<String>,
+    occupied: bool,
+    writes_left: Option<usize>,
+    transactional: bool,
+    transactions: usize,
+    in_transaction: bool,
 }
 
 #[async_trait::async_trait]
@@ -16,37 +24,93 @@ impl EntityStore for TestStore {
         println!("store: get {} (local: {})", path, local);
         self.reads.insert(path.to_owned());
 
+        if self.occupied && !self.data.contains_key(&path) {
+            let mut map = HashMap::new();
+            map.insert(path.to_owned(), Entity::new(path.to_owned()));
+
+            return Ok(Some(StoreItem::new(path, map)));
+        }
+
         Ok(self.data.get(&path).cloned())
     }
 
     async fn put(&mut self, path: String, item: &mut StoreItem) -> Result<(), StoreError> {
         println!("store: put {}", path);
+        self.write()?;
         self.data.insert(path, item.clone());
 
         Ok(())
     }
 
-    async fn query(&mut self, _query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError> {
-        Err("not implemented".into())
+    async fn query(&mut self, query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError> {
+        let rows = self.select(Query::new(query)).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|f| f.unwrap_or_default()).collect())
+            .collect())
     }
 
+    async fn select(&mut self, query: Query) -> Result<Vec<Vec<Option<String>>>, StoreError> {
+        println!("store: select {:?}", query);
+        let quads: Vec<_> = self.data.values().flat_map(item_quads).collect();
+
+        Ok(evaluate_query(&query, &quads))
+    }
+
+    /// Collections are read newest first, and cursors are offsets into the collection.
     async fn read_collection(
         &mut self,
         path: String,
-        _count: Option<u32>,
-        _cursor: Option<String>,
+        count: Option<u32>,
+        cursor: Option<String>,
     ) -> Result<CollectionPointer, StoreError> {
-        println!("store: read collection {}", path);
-
-        Err("not implemented".into())
+        println!("store: read collection {} (cursor: {:?})", path, cursor);
+
+        let items: Vec<_> = match self.items.get(&path) {
+            Some(items) => items.iter().rev().cloned().collect(),
+            None => vec![],
+        };
+
+        let count = count.unwrap_or(20) as usize;
+        let start = match cursor {
+            None => 0,
+            Some(cursor) => cursor.parse().map_err(|_| "invalid cursor")?,
+        };
+
+        let start = start.min(items.len());
+        let end = (start + count).min(items.len());
+
+        Ok(CollectionPointer {
+            items: items[start..end].to_vec(),
+            after: if end < items.len() {
+                Some(end.to_string())
+            } else {
+                None
+            },
+            before: if start > 0 {
+                Some(start.saturating_sub(count).to_string())
+            } else {
+                None
+            },
+            count: Some(items.len() as u32),
+        })
     }
 
     async fn find_collection(
         &mut self,
-        _path: String,
-        _item: String,
+        path: String,
+        item: String,
     ) -> Result<CollectionPointer, StoreError> {
-        Err("not implemented".into())
+        println!("store: find collection {}, item {}", path, item);
+        let found = self.contains(&path, &item);
+
+        Ok(CollectionPointer {
+            items: if found { vec![item] } else { vec![] },
+            after: None,
+            before: None,
+            count: None,
+        })
     }
 
     async fn read_collection_inverse(
@@ -58,6 +122,7 @@ impl EntityStore for TestStore {
 
     async fn insert_collection(&mut self, path: String, item: String) -> Result<(), StoreError> {
         println!("store: insert collection {}, item {}", path, item);
+        self.write()?;
         if let None = self.items.get(&path) {
             self.items.insert(path.to_owned(), vec![]);
         }
@@ -72,6 +137,7 @@ impl EntityStore for TestStore {
 
     async fn remove_collection(&mut self, path: String, item: String) -> Result<(), StoreError> {
         println!("store: remove collection {}, item {}", path, item);
+        self.write()?;
         if let None = self.items.get(&path) {
             return Ok(());
         }
@@ -83,6 +149,35 @@ impl EntityStore for TestStore {
 
         Ok(())
     }
+
+    /// Transactions are only counted, changes are never rolled back. Like most
+    ///  databases, transactions can't be nested.
+    async fn begin_transaction(&mut self) -> Result<bool, StoreError> {
+        if !self.transactional {
+            return Ok(false);
+        }
+
+        if self.in_transaction {
+            return Err("already in a transaction".into());
+        }
+
+        self.in_transaction = true;
+        self.transactions += 1;
+
+        Ok(true)
+    }
+
+    async fn commit_transaction(&mut self) -> Result<(), StoreError> {
+        self.in_transaction = false;
+
+        Ok(())
+    }
+
+    async fn rollback_transaction(&mut self) -> Result<(), StoreError> {
+        self.in_transaction = false;
+
+        Ok(())
+    }
 }
 
 impl TestStore {
@@ -91,6 +186,52 @@ impl TestStore {
             data: data.into_iter().map(|f| (f.id().to_owned(), f)).collect(),
             items: HashMap::new(),
             reads: HashSet::new(),
+            occupied: false,
+            writes_left: None,
+            transactional: false,
+            transactions: 0,
+            in_transaction: false,
+        }
+    }
+
+    /// Creates a store that fails every write after the first `writes`.
+    pub fn failing_after(data: Vec<StoreItem>, writes: usize) -> TestStore {
+        TestStore {
+            writes_left: Some(writes),
+            ..TestStore::new(data)
+        }
+    }
+
+    fn write(&mut self) -> Result<(), StoreError> {
+        match &mut self.writes_left {
+            Some(0) => Err("store failed".into()),
+            Some(writes) => {
+                *writes -= 1;
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Creates a store that supports transactions, see `transactions`.
+    pub fn transactional(data: Vec<StoreItem>) -> TestStore {
+        TestStore {
+            transactional: true,
+            ..TestStore::new(data)
+        }
+    }
+
+    /// The amount of transactions that have been started.
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+
+    /// Creates a store in which every ID is in use, returning an empty
+    ///  object for any ID not in `data`.
+    pub fn occupied(data: Vec<StoreItem>) -> TestStore {
+        TestStore {
+            occupied: true,
+            ..TestStore::new(data)
         }
     }
 
@@ -101,6 +242,10 @@ impl TestStore {
             .unwrap_or(false)
     }
 
+    pub fn item(&self, val: &str) -> Option<&StoreItem> {
+        self.data.get(&String::from(val))
+    }
+
     pub fn has_read(&self, val: &str) -> bool {
         self.reads.contains(&String::from(val))
     }
@@ -129,6 +274,7 @@ impl TestStore {
             name: String::new(),
             description: String::new(),
             instance_id: 1,
+            id_strategy: Arc::new(HierarchicalIds),
             entity_store: self,
             queue_store: queue,
         }
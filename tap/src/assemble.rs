@@ -3,23 +3,127 @@ use serde_json::json;
 use serde_json::Map as JMap;
 use serde_json::Value as JValue;
 use std::collections::{HashMap, HashSet};
-use std::error::Error;
 use std::future::Future;
 use std::pin::Pin;
 
 use crate::auth::Authorizer;
 use crate::entity::StoreItem;
 use crate::entitystore::StoreError;
+use crate::error::TapError;
 use crate::id::get_suggestion;
 use crate::user::Context;
 
-async fn _get_collectionified(
+/// The parameters of a collection page, which are passed in its ID after
+///  the collection, e.g. `https://example.com/outbox&cursor=abc&count=20`.
+#[derive(Debug, Default, PartialEq)]
+struct PageParams {
+    cursor: Option<String>,
+    count: Option<u32>,
+    last: bool,
+}
+
+/// The largest amount of items on a single collection page. Larger counts
+///  requested in a page ID are lowered to this.
+pub const MAX_PAGE_SIZE: u32 = 100;
+
+/// The amount of items on a collection page if its ID doesn't specify one.
+pub const DEFAULT_PAGE_SIZE: u32 = 20;
+
+/// Percent-encodes everything but unreserved characters, so a cursor can be
+///  used as a parameter in a page ID.
+fn percent_encode(value: &str) -> String {
+    let mut result = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                result.push(byte as char)
+            }
+            byte => result.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+
+    result
+}
+
+/// Decodes a percent-encoded parameter. Returns `None` if it isn't valid.
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::new();
+    let mut iter = value.bytes();
+    while let Some(byte) = iter.next() {
+        if byte == b'%' {
+            let hex = [iter.next()?, iter.next()?];
+            let hex = std::str::from_utf8(&hex).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+impl PageParams {
+    /// Parses the parameters after the first `&`. `last` selects the last
+    ///  page, and unknown parameters (like `first`) are ignored, as are
+    ///  cursors that aren't percent-encoded properly. Counts are limited to
+    ///  `MAX_PAGE_SIZE`.
+    fn parse(params: &str) -> PageParams {
+        let mut result = PageParams::default();
+
+        for param in params.split('&') {
+            let mut split = param.splitn(2, '=');
+            match (split.next(), split.next()) {
+                (Some("cursor"), Some(cursor)) if !cursor.is_empty() => {
+                    result.cursor = percent_decode(cursor)
+                }
+                (Some("count"), Some(count)) => {
+                    result.count = count.parse().ok().map(|f: u32| f.min(MAX_PAGE_SIZE))
+                }
+                (Some("last"), None) => result.last = true,
+                _ => {}
+            }
+        }
+
+        result
+    }
+}
+
+/// The ID of the page of a collection starting at a cursor.
+fn page_id(collection: &str, cursor: &str, count: u32) -> String {
+    format!(
+        "{}&cursor={}&count={}",
+        collection,
+        percent_encode(cursor),
+        count
+    )
+}
+
+/// Gets an item from the store, like `EntityStore::get`. IDs of the form
+///  `collection&cursor=...&count=...`, `collection&first` and
+///  `collection&last` are turned into an `OrderedCollectionPage` of that
+///  collection, and collections get `first` and `last` links to their pages.
+///  Pages hold `DEFAULT_PAGE_SIZE` items unless the ID asks for another count.
+pub async fn get_collectionified(
     context: &mut Context<'_, '_>,
     id: &str,
 ) -> Result<Option<StoreItem>, StoreError> {
     let without_query = id.split('&').next().unwrap().to_string();
     if without_query == id {
-        context.entity_store.get(id.to_owned(), true).await
+        let mut item = context.entity_store.get(id.to_owned(), true).await?;
+
+        if let Some(item) = &mut item {
+            let main = item.main_mut();
+            if main.types.contains(&as2!(OrderedCollection).to_string()) {
+                for (key, page) in &[(as2!(first), "first"), (as2!(last), "last")] {
+                    if main[*key].is_empty() {
+                        main.get_mut(key)
+                            .push(Pointer::Id(format!("{}&{}", id, page)));
+                    }
+                }
+            }
+        }
+
+        Ok(item)
     } else {
         if let Some(val) = context
             .entity_store
@@ -34,22 +138,55 @@ async fn _get_collectionified(
                 return Ok(None);
             }
 
-            let data = context
+            let params = PageParams::parse(&id[without_query.len() + 1..]);
+            let count = params.count.unwrap_or(DEFAULT_PAGE_SIZE);
+            let mut data = context
                 .entity_store
-                .read_collection(without_query.clone(), None, None)
+                .read_collection(without_query.clone(), Some(count), params.cursor)
                 .await?;
 
+            // Stores can't start reading at the end of a collection, so the last
+            //  page is found by following the pages up to it.
+            if params.last {
+                while let Some(after) = data.after.clone() {
+                    let next = context
+                        .entity_store
+                        .read_collection(without_query.clone(), Some(count), Some(after))
+                        .await?;
+
+                    if next.items.is_empty() {
+                        data.after = None;
+                    } else {
+                        data = next;
+                    }
+                }
+            }
+
+            let items: Vec<_> = data.items.iter().map(|f| json!({ "@id": f })).collect();
+            let mut page = json!({
+                "@id": id,
+                "@type": [as2!(OrderedCollectionPage)],
+                as2!(partOf): [{"@id": without_query}],
+                as2!(orderedItems): [{"@list": items}]
+            });
+
+            if let Some(after) = &data.after {
+                page[as2!(next)] = json!([{"@id": page_id(&without_query, after, count)}]);
+            }
+
+            if let Some(before) = &data.before {
+                page[as2!(prev)] = json!([{"@id": page_id(&without_query, before, count)}]);
+            }
+
+            if let Some(count) = data.count {
+                page[as2!(totalItems)] = json!([{
+                    "@value": count,
+                    "@type": "http://www.w3.org/2001/XMLSchema#nonNegativeInteger"
+                }]);
+            }
+
             Ok(Some(
-                StoreItem::parse(
-                    &id,
-                    &json!({
-                        "@id": id,
-                        "@type": [as2!(OrderedCollectionPage)],
-                        as2!(partOf): [{"@id": without_query}],
-                        "orderedItems": [{"@list": data.items}]
-                    }),
-                )
-                .expect("static input cannot fail"),
+                StoreItem::parse(id, &page).expect("static input cannot fail"),
             ))
         } else {
             Ok(None)
@@ -57,7 +194,8 @@ async fn _get_collectionified(
     }
 }
 
-const AVOID_ASSEMBLE: [&'static str; 12] = [
+/// The predicates that aren't embedded by default.
+const AVOID_ASSEMBLE: [&'static str; 15] = [
     as2!(url),
     ldp!(inbox),
     as2!(outbox),
@@ -70,44 +208,159 @@ const AVOID_ASSEMBLE: [&'static str; 12] = [
     as2!(bto),
     as2!(bcc),
     "http://ostatus.org/#conversation",
+    as2!(next),
+    as2!(prev),
+    as2!(last),
 ];
 
+/// The depth used for the values of predicates that are never embedded,
+///  which ensures nothing below them is embedded either.
+const REFERENCE_DEPTH: u32 = u32::MAX;
+
+/// How the objects a predicate points to are assembled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbedRule {
+    /// Embed the objects, as long as the maximum depth isn't reached.
+    Embed,
+
+    /// Never embed the objects, only refer to them by ID.
+    Reference,
+
+    /// Always embed the objects, regardless of depth or flat mode.
+    Always,
+}
+
+/// Controls which objects are embedded by `assemble_with`. Objects that are
+///  stored as part of the item itself, like the blank nodes in it, are always
+///  embedded, and collections are never embedded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AssembleOptions {
+    /// Objects are only fetched and embedded while the depth is below this.
+    pub max_depth: u32,
+
+    /// The rules for specific predicates. Other predicates use
+    ///  `EmbedRule::Embed`.
+    pub predicates: HashMap<String, EmbedRule>,
+
+    /// Always embed blank nodes, as they can't be fetched by ID.
+    pub embed_blank_nodes: bool,
+
+    /// Always embed `sec:publicKey`, so the key can be used without another
+    ///  request.
+    pub embed_public_keys: bool,
+
+    /// Never embed objects, unless their predicate has `EmbedRule::Always`,
+    ///  or they're blank nodes and `embed_blank_nodes` is set.
+    pub flat: bool,
+}
+
+impl AssembleOptions {
+    /// The options used by `assemble`.
+    pub fn new() -> AssembleOptions {
+        AssembleOptions {
+            max_depth: 5,
+            predicates: AVOID_ASSEMBLE
+                .iter()
+                .map(|f| (f.to_string(), EmbedRule::Reference))
+                .collect(),
+            embed_blank_nodes: true,
+            embed_public_keys: false,
+            flat: false,
+        }
+    }
+
+    /// Options that only embed blank nodes and public keys.
+    pub fn flat() -> AssembleOptions {
+        AssembleOptions {
+            embed_public_keys: true,
+            flat: true,
+            ..AssembleOptions::new()
+        }
+    }
+
+    pub fn with_max_depth(mut self, max_depth: u32) -> AssembleOptions {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Sets the rule for a predicate, replacing the previous one.
+    pub fn with_rule(mut self, predicate: &str, rule: EmbedRule) -> AssembleOptions {
+        self.predicates.insert(predicate.to_owned(), rule);
+        self
+    }
+
+    /// The rule that applies to a predicate.
+    pub fn rule(&self, predicate: &str) -> EmbedRule {
+        if self.embed_public_keys && predicate == sec!(publicKey) {
+            return EmbedRule::Always;
+        }
+
+        self.predicates
+            .get(predicate)
+            .cloned()
+            .unwrap_or(EmbedRule::Embed)
+    }
+
+    /// Checks if the object with this ID should be fetched to be embedded.
+    fn embeds(&self, id: &str, rule: EmbedRule, depth: u32) -> bool {
+        let embed = match rule {
+            EmbedRule::Always => true,
+            EmbedRule::Reference => false,
+            EmbedRule::Embed => !self.flat && depth < self.max_depth,
+        };
+
+        embed || (self.embed_blank_nodes && id.starts_with("_:"))
+    }
+}
+
+impl Default for AssembleOptions {
+    fn default() -> AssembleOptions {
+        AssembleOptions::new()
+    }
+}
+
+/// What stays the same while assembling a single item: who it's assembled
+///  for, the objects that have been embedded already, and the options.
+struct AssembleState<'a, R> {
+    authorizer: &'a R,
+    seen: &'a mut HashSet<String>,
+    options: &'a AssembleOptions,
+}
+
 /// Assemble a single [`Pointer`], avoiding cycles and repeating objects.
-fn _assemble_val<'a, 'b, 'c, 'd, 'e, 'out, R: Authorizer>(
+fn _assemble_val<'a, 'b, 'c, 'd, 's, 'out, R: Authorizer>(
     value: &'a Pointer,
     depth: u32,
+    rule: EmbedRule,
     items: &'b HashMap<String, Entity>,
     context: &'c mut Context<'_, '_>,
-    authorizer: &'d R,
-    seen: &'e mut HashSet<String>,
-) -> Pin<
-    Box<dyn Future<Output = Result<JValue, Box<dyn Error + Send + Sync + 'static>>> + Send + 'out>,
->
+    state: &'d mut AssembleState<'s, R>,
+) -> Pin<Box<dyn Future<Output = Result<JValue, TapError>> + Send + 'out>>
 where
     'a: 'out,
     'b: 'out,
     'c: 'out,
     'd: 'out,
-    'e: 'out,
+    's: 'out,
 {
     Box::pin(async move {
         match value {
             Pointer::Id(id) => {
-                if seen.contains(id) {
+                if state.seen.contains(id) {
                     let mut hash = JMap::new();
                     hash.insert("@id".to_owned(), JValue::String((*id).clone()));
                     return Ok(JValue::Object(hash));
                 } else if items.contains_key(id) {
                     let item = items.get(id).unwrap();
-                    return _assemble(item, depth + 1, context, items, authorizer, seen).await;
+                    return _assemble(item, depth.saturating_add(1), context, items, state).await;
                 }
-                if depth < 5 || id.starts_with("_:") {
-                    let item = _get_collectionified(context, &id).await?;
+                if state.options.embeds(id, rule, depth) {
+                    let item = get_collectionified(context, &id).await?;
                     if let Some(item) = item {
                         let can_show = if item.id().starts_with("_:") {
                             true
                         } else {
-                            authorizer.can_show(context, &item).await?
+                            state.authorizer.can_show(context, &item).await?
                         };
 
                         if !can_show {
@@ -117,7 +370,7 @@ where
                             return Ok(JValue::Object(hash));
                         }
 
-                        seen.insert(id.to_owned());
+                        state.seen.insert(id.to_owned());
 
                         if !item
                             .main()
@@ -125,12 +378,17 @@ where
                             .contains(&as2!(OrderedCollection).to_string())
                         {
                             let is_blank = item.id().starts_with("_:");
-                            return assemble(
+                            return assemble_with(
                                 &item,
-                                if is_blank { depth } else { depth + 1 },
+                                if is_blank {
+                                    depth
+                                } else {
+                                    depth.saturating_add(1)
+                                },
                                 context,
-                                authorizer,
-                                seen,
+                                state.authorizer,
+                                state.seen,
+                                state.options,
                             )
                             .await;
                         }
@@ -149,7 +407,7 @@ where
             Pointer::List(list) => {
                 let mut vals = Vec::new();
                 for item in list {
-                    let res = _assemble_val(item, depth, items, context, authorizer, seen).await?;
+                    let res = _assemble_val(item, depth, rule, items, context, state).await?;
                     vals.push(res);
                 }
 
@@ -166,12 +424,11 @@ async fn _assemble<R: Authorizer>(
     depth: u32,
     context: &mut Context<'_, '_>,
     items: &HashMap<String, Entity>,
-    authorizer: &R,
-    seen: &mut HashSet<String>,
-) -> Result<JValue, Box<dyn Error + Send + Sync + 'static>> {
+    state: &mut AssembleState<'_, R>,
+) -> Result<JValue, TapError> {
     let mut map = JMap::new();
     if !item.id.starts_with("_:") {
-        seen.insert(item.id.to_owned());
+        state.seen.insert(item.id.to_owned());
         map.insert("@id".to_owned(), JValue::String(item.id.to_owned()));
     }
 
@@ -192,14 +449,15 @@ async fn _assemble<R: Authorizer>(
     for (key, values) in item.iter() {
         let mut out = Vec::new();
 
-        for value in values {
-            let depth = if AVOID_ASSEMBLE.contains(&(&key as &str)) {
-                999
-            } else {
-                depth
-            };
+        let rule = state.options.rule(key);
+        let depth = if rule == EmbedRule::Reference {
+            REFERENCE_DEPTH
+        } else {
+            depth
+        };
 
-            let res = _assemble_val(value, depth, items, context, authorizer, seen).await?;
+        for value in values {
+            let res = _assemble_val(value, depth, rule, items, context, state).await?;
             out.push(res);
         }
 
@@ -216,10 +474,36 @@ pub async fn assemble<R: Authorizer>(
     context: &mut Context<'_, '_>,
     authorizer: &R,
     seen: &mut HashSet<String>,
-) -> Result<JValue, Box<dyn Error + Send + Sync + 'static>> {
+) -> Result<JValue, TapError> {
+    assemble_with(
+        item,
+        depth,
+        context,
+        authorizer,
+        seen,
+        &AssembleOptions::default(),
+    )
+    .await
+}
+
+/// Assembles a `StoreItem` like `assemble`, using `options` to decide which
+///  objects are embedded.
+pub async fn assemble_with<R: Authorizer>(
+    item: &StoreItem,
+    depth: u32,
+    context: &mut Context<'_, '_>,
+    authorizer: &R,
+    seen: &mut HashSet<String>,
+    options: &AssembleOptions,
+) -> Result<JValue, TapError> {
     let main = item.data.get(&item.id).unwrap();
+    let mut state = AssembleState {
+        authorizer,
+        seen,
+        options,
+    };
 
-    _assemble(main, depth, context, &item.data, authorizer, seen).await
+    _assemble(main, depth, context, &item.data, &mut state).await
 }
 
 // Finds all the IDs referenced in the tangle.
@@ -412,3 +696,251 @@ pub fn untangle(data: &JValue) -> Result<HashMap<String, StoreItem>, NodeMapErro
         })
         .collect())
 }
+
+#[cfg(test)]
+mod test {
+    use super::{
+        assemble_with, get_collectionified, page_id, AssembleOptions, EmbedRule, PageParams,
+        DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+    };
+    use crate::entity::StoreItem;
+    use crate::test::{entity, item, TestStore};
+    use async_std::task::block_on;
+    use jsonld::nodemap::Pointer;
+    use serde_json::Value as JValue;
+    use std::collections::HashSet;
+
+    #[test]
+    fn applies_embed_rules() {
+        let options = AssembleOptions::new().with_rule(as2!(object), EmbedRule::Always);
+
+        assert_eq!(options.rule(as2!(to)), EmbedRule::Reference);
+        assert_eq!(options.rule(as2!(object)), EmbedRule::Always);
+        assert_eq!(options.rule(sec!(publicKey)), EmbedRule::Embed);
+
+        assert!(options.embeds("/a", EmbedRule::Embed, 4));
+        assert!(!options.embeds("/a", EmbedRule::Embed, 5));
+        assert!(!options.embeds("/a", EmbedRule::Reference, 0));
+        assert!(options.embeds("_:b0", EmbedRule::Reference, 0));
+        assert!(options.embeds("/a", EmbedRule::Always, 100));
+    }
+
+    #[test]
+    fn flat_only_embeds_always() {
+        let options = AssembleOptions::flat();
+
+        assert!(!options.embeds("/a", EmbedRule::Embed, 0));
+        assert!(options.embeds("_:b0", EmbedRule::Embed, 0));
+        assert!(options.embeds("/a#key", options.rule(sec!(publicKey)), 0));
+
+        let options = AssembleOptions {
+            embed_blank_nodes: false,
+            ..options
+        };
+        assert!(!options.embeds("_:b0", EmbedRule::Embed, 0));
+    }
+
+    #[test]
+    fn parses_page_params() {
+        assert_eq!(PageParams::parse("first"), PageParams::default());
+        assert_eq!(
+            PageParams::parse("cursor=abc&count=20"),
+            PageParams {
+                cursor: Some("abc".to_owned()),
+                count: Some(20),
+                last: false,
+            }
+        );
+        assert_eq!(
+            PageParams::parse("cursor=%ZZ&count=100000"),
+            PageParams {
+                cursor: None,
+                count: Some(MAX_PAGE_SIZE),
+                last: false,
+            }
+        );
+        assert_eq!(
+            PageParams::parse("last&count=x"),
+            PageParams {
+                cursor: None,
+                count: None,
+                last: true,
+            }
+        );
+    }
+
+    #[test]
+    fn page_ids_round_trip() {
+        let id = page_id("https://example.com/outbox", "abc", 20);
+        assert_eq!(id, "https://example.com/outbox&cursor=abc&count=20");
+
+        let params = PageParams::parse(id.split_once('&').unwrap().1);
+        assert_eq!(params.cursor.as_deref(), Some("abc"));
+        assert_eq!(params.count, Some(20));
+
+        let cursor = "2019-01-01T00:00:00+01:00&count=1 /ü";
+        let id = page_id("https://example.com/outbox", cursor, 1);
+        assert_eq!(id.matches('&').count(), 2);
+
+        let params = PageParams::parse(id.split_once('&').unwrap().1);
+        assert_eq!(params.cursor.as_deref(), Some(cursor));
+        assert_eq!(params.count, Some(1));
+    }
+
+    fn outbox(items: &[&str]) -> TestStore {
+        TestStore::new(vec![entity("/outbox", &[as2!(OrderedCollection)], &[])])
+            .with_collection("/outbox", items)
+    }
+
+    fn get(store: &mut TestStore, id: &str) -> StoreItem {
+        let mut queue = ();
+        let mut context = store.context(&mut queue, "/subject");
+
+        block_on(get_collectionified(&mut context, id))
+            .unwrap()
+            .expect("page does not exist")
+    }
+
+    fn ids(item: &StoreItem, predicate: &str) -> Vec<String> {
+        let mut result = Vec::new();
+        for pointer in &item.main()[predicate] {
+            match pointer {
+                Pointer::Id(id) => result.push(id.to_owned()),
+                Pointer::List(list) => result.extend(list.iter().filter_map(|f| match f {
+                    Pointer::Id(id) => Some(id.to_owned()),
+                    _ => None,
+                })),
+                _ => {}
+            }
+        }
+
+        result
+    }
+
+    fn total(item: &StoreItem) -> Option<u64> {
+        match item.main()[as2!(totalItems)].first() {
+            Some(Pointer::Value(value)) => value.value.as_u64(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn links_collection_pages() {
+        let mut store = outbox(&["/a", "/b", "/c"]);
+        let collection = get(&mut store, "/outbox");
+
+        assert_eq!(ids(&collection, as2!(first)), vec!["/outbox&first"]);
+        assert_eq!(ids(&collection, as2!(last)), vec!["/outbox&last"]);
+    }
+
+    #[test]
+    fn pages_through_collection() {
+        let mut store = outbox(&["/a", "/b", "/c"]);
+
+        let first = get(&mut store, "/outbox&first&count=2");
+        assert_eq!(ids(&first, as2!(orderedItems)), vec!["/c", "/b"]);
+        assert_eq!(ids(&first, as2!(partOf)), vec!["/outbox"]);
+        assert_eq!(ids(&first, as2!(prev)), Vec::<String>::new());
+        assert_eq!(total(&first), Some(3));
+
+        let next = ids(&first, as2!(next));
+        assert_eq!(next, vec!["/outbox&cursor=2&count=2"]);
+
+        let second = get(&mut store, &next[0]);
+        assert_eq!(ids(&second, as2!(orderedItems)), vec!["/a"]);
+        assert_eq!(ids(&second, as2!(next)), Vec::<String>::new());
+        assert_eq!(ids(&second, as2!(prev)), vec!["/outbox&cursor=0&count=2"]);
+        assert_eq!(total(&second), Some(3));
+
+        let last = get(&mut store, "/outbox&last&count=2");
+        assert_eq!(ids(&last, as2!(orderedItems)), vec!["/a"]);
+        assert_eq!(ids(&last, as2!(prev)), vec!["/outbox&cursor=0&count=2"]);
+    }
+
+    #[test]
+    fn limits_page_size() {
+        let items: Vec<_> = (0..MAX_PAGE_SIZE + 1).map(|f| format!("/{}", f)).collect();
+        let items: Vec<_> = items.iter().map(|f| f as &str).collect();
+        let mut store = outbox(&items);
+
+        let first = get(&mut store, "/outbox&first");
+        assert_eq!(
+            ids(&first, as2!(orderedItems)).len(),
+            DEFAULT_PAGE_SIZE as usize
+        );
+
+        let first = get(&mut store, "/outbox&count=1000");
+        assert_eq!(
+            ids(&first, as2!(orderedItems)).len(),
+            MAX_PAGE_SIZE as usize
+        );
+        assert_eq!(ids(&first, as2!(next)).len(), 1);
+    }
+
+    fn assemble_create(options: &AssembleOptions) -> JValue {
+        let mut store = TestStore::new(vec![
+            entity("/note", &[as2!(Note)], &[(as2!(attributedTo), &["/actor"])]),
+            entity(
+                "/actor",
+                &[as2!(Person)],
+                &[(sec!(publicKey), &["/actor/key"])],
+            ),
+            entity("/actor/key", &[sec!(Key)], &[]),
+        ]);
+        let create = item(entity(
+            "/create",
+            &[as2!(Create)],
+            &[(as2!(object), &["/note"])],
+        ));
+
+        let mut queue = ();
+        let mut context = store.context(&mut queue, "/subject");
+        block_on(assemble_with(
+            &create,
+            0,
+            &mut context,
+            &(),
+            &mut HashSet::new(),
+            options,
+        ))
+        .unwrap()
+    }
+
+    fn is_embedded(value: &JValue) -> bool {
+        value.get("@type").is_some()
+    }
+
+    #[test]
+    fn assembles_nested_objects() {
+        let create = assemble_create(&AssembleOptions::new());
+        let note = &create[as2!(object)][0];
+        let actor = &note[as2!(attributedTo)][0];
+
+        assert!(is_embedded(note));
+        assert!(is_embedded(actor));
+        assert!(is_embedded(&actor[sec!(publicKey)][0]));
+    }
+
+    #[test]
+    fn assembles_flat() {
+        let create = assemble_create(&AssembleOptions::flat());
+        let note = &create[as2!(object)][0];
+
+        assert!(!is_embedded(note), "Flat mode embedded an object");
+        assert_eq!(note["@id"], "/note");
+    }
+
+    #[test]
+    fn assembles_up_to_max_depth() {
+        let create = assemble_create(&AssembleOptions::new().with_max_depth(1));
+        let note = &create[as2!(object)][0];
+        let actor = &note[as2!(attributedTo)][0];
+
+        assert!(is_embedded(note));
+        assert!(
+            !is_embedded(actor),
+            "Embedded an object beyond the max depth"
+        );
+        assert_eq!(actor["@id"], "/actor");
+    }
+}
@@ -1,9 +1,10 @@
 use crate::entity::StoreItem;
+use crate::error::TapError;
 use crate::user::Context;
+use crate::visibility::{addressees, visibility, Visibility};
 
 use jsonld::nodemap::{Pointer, Value};
 use serde_json::Value as JValue;
-use std::error::Error;
 
 #[async_trait::async_trait]
 pub trait Authorizer: Send + Sync + 'static {
@@ -11,18 +12,14 @@ pub trait Authorizer: Send + Sync + 'static {
         &self,
         context: &mut Context<'_, '_>,
         entity: &StoreItem,
-    ) -> Result<bool, Box<dyn Error + Send + Sync + 'static>>;
+    ) -> Result<bool, TapError>;
 
     fn can_replace(&self, old: &StoreItem, new: &StoreItem) -> bool;
 }
 
 #[async_trait::async_trait]
 impl Authorizer for () {
-    async fn can_show(
-        &self,
-        _: &mut Context<'_, '_>,
-        _: &StoreItem,
-    ) -> Result<bool, Box<dyn Error + Send + Sync + 'static>> {
+    async fn can_show(&self, _: &mut Context<'_, '_>, _: &StoreItem) -> Result<bool, TapError> {
         Ok(true)
     }
 
@@ -31,6 +28,13 @@ impl Authorizer for () {
     }
 }
 
+/// Shows entities based on their `Visibility`. Public and unlisted entities can be seen
+///  by anyone, followers-only and limited ones by their authors, addressees, and the
+///  members of the collections they're addressed to, and direct ones by their authors
+///  and addressees only.
+///
+/// Entities that have neither addressing nor an author, like actors and collections,
+///  can be seen by anyone.
 pub struct DefaultAuthorizer;
 
 #[async_trait::async_trait]
@@ -39,49 +43,52 @@ impl Authorizer for DefaultAuthorizer {
         &self,
         context: &mut Context<'_, '_>,
         entity: &StoreItem,
-    ) -> Result<bool, Box<dyn Error + Send + Sync + 'static>> {
-        let mut audience = Vec::new();
-        let mut has_non_actor = false;
-        for item in &[
-            as2!(to),
-            as2!(cc),
-            as2!(bcc),
-            as2!(bto),
-            as2!(actor),
-            as2!(object),
-            as2!(attributedTo),
-        ] {
-            for it in &entity.main()[item] {
-                if item != &as2!(actor) && item != &as2!(attributedTo) && item != &as2!(object) {
-                    has_non_actor = true;
-                }
+    ) -> Result<bool, TapError> {
+        let main = entity.main();
+        let addressed = addressees(main);
 
-                if let Pointer::Id(it) = it {
-                    audience.push(it.to_owned());
+        let mut involved = Vec::new();
+        for predicate in &[as2!(actor), as2!(attributedTo), as2!(object)] {
+            for pointer in &main[predicate] {
+                if let Pointer::Id(id) = pointer {
+                    involved.push(id.to_owned());
                 }
             }
         }
 
-        if !has_non_actor {
-            Ok(true)
-        } else if audience.contains(&as2!(Public).to_string())
-            || audience.contains(&context.user.subject)
+        if addressed.is_empty()
+            && main[as2!(actor)].is_empty()
+            && main[as2!(attributedTo)].is_empty()
         {
-            Ok(true)
-        } else {
-            for id in audience {
-                let data = context
-                    .entity_store
-                    .find_collection(id, context.user.subject.clone())
-                    .await?;
-
-                if !data.items.is_empty() {
-                    return Ok(true);
-                }
-            }
+            return Ok(true);
+        }
 
-            Ok(false)
+        let visibility = visibility(context, entity).await?;
+        if visibility.is_public() {
+            return Ok(true);
         }
+
+        let subject = context.user.subject.to_owned();
+        if addressed.contains(&subject) || involved.contains(&subject) {
+            return Ok(true);
+        }
+
+        if visibility == Visibility::Direct {
+            return Ok(false);
+        }
+
+        for id in addressed {
+            let data = context
+                .entity_store
+                .find_collection(id, subject.to_owned())
+                .await?;
+
+            if !data.items.is_empty() {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
     }
 
     fn can_replace(&self, old: &StoreItem, new: &StoreItem) -> bool {
@@ -133,7 +140,7 @@ impl<R: Authorizer> Authorizer for LocalOnlyAuthorizer<R> {
         &self,
         context: &mut Context<'_, '_>,
         entity: &StoreItem,
-    ) -> Result<bool, Box<dyn Error + Send + Sync + 'static>> {
+    ) -> Result<bool, TapError> {
         let is_local = match entity
             .sub(kroeg!(meta))
             .and_then(|f| f[kroeg!(instance)].get(0))
@@ -159,3 +166,91 @@ impl<R: Authorizer> Authorizer for LocalOnlyAuthorizer<R> {
         self.0.can_replace(old, new)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::{Authorizer, DefaultAuthorizer};
+    use crate::test::{entity, item, Pairs, TestStore};
+    use async_std::task::block_on;
+
+    fn store() -> TestStore {
+        TestStore::new(vec![
+            entity(
+                "/actor",
+                &[as2!(Person)],
+                &[(as2!(followers), &["/actor/followers"])],
+            ),
+            entity("/actor/followers", &[as2!(OrderedCollection)], &[]),
+            entity("/friends", &[as2!(Collection)], &[]),
+            entity("/friend", &[as2!(Person)], &[]),
+        ])
+        .with_collection("/actor/followers", &["/follower"])
+        .with_collection("/friends", &["/member"])
+    }
+
+    fn can_show(pairs: Pairs, viewer: &str) -> bool {
+        let mut store = store();
+        let mut queue = ();
+        let mut context = store.context(&mut queue, viewer);
+
+        let mut pairs = pairs.to_vec();
+        pairs.push((as2!(attributedTo), &["/actor"]));
+        let note = item(entity("/note", &[as2!(Note)], &pairs));
+
+        block_on(DefaultAuthorizer.can_show(&mut context, &note)).unwrap()
+    }
+
+    #[test]
+    fn shows_public_and_unlisted() {
+        assert!(can_show(&[(as2!(to), &[as2!(Public)])], "/stranger"));
+        assert!(can_show(
+            &[
+                (as2!(to), &["/actor/followers"]),
+                (as2!(cc), &[as2!(Public)])
+            ],
+            "/stranger"
+        ));
+    }
+
+    #[test]
+    fn shows_followers_only_to_followers() {
+        let pairs: Pairs = &[(as2!(to), &["/actor/followers"])];
+
+        assert!(can_show(pairs, "/follower"));
+        assert!(can_show(pairs, "/actor"));
+        assert!(!can_show(pairs, "/stranger"));
+    }
+
+    #[test]
+    fn shows_limited_to_members() {
+        let pairs: Pairs = &[(as2!(to), &["/friends"])];
+
+        assert!(can_show(pairs, "/member"));
+        assert!(!can_show(pairs, "/stranger"));
+    }
+
+    #[test]
+    fn shows_direct_to_addressees() {
+        let pairs: Pairs = &[(as2!(to), &["/friend"])];
+
+        assert!(can_show(pairs, "/friend"));
+        assert!(can_show(pairs, "/actor"));
+        assert!(!can_show(pairs, "/stranger"));
+    }
+
+    #[test]
+    fn shows_unaddressed_to_author() {
+        assert!(can_show(&[], "/actor"));
+        assert!(!can_show(&[], "/stranger"));
+    }
+
+    #[test]
+    fn shows_unauthored_entities() {
+        let mut store = store();
+        let mut queue = ();
+        let mut context = store.context(&mut queue, "/stranger");
+        let actor = item(entity("/friend", &[as2!(Person)], &[]));
+
+        assert!(block_on(DefaultAuthorizer.can_show(&mut context, &actor)).unwrap());
+    }
+}
@@ -78,6 +78,11 @@ impl StoreItem {
         self.data.get_mut(id)
     }
 
+    /// The IDs of all entities in this store item, in no particular order.
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.data.keys().map(|f| f.as_str())
+    }
+
     /// Creates a new sub-item with a randomly assigned blank node.
     pub fn create(&mut self) -> &mut Entity {
         let id = loop {
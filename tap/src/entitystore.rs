@@ -1,15 +1,17 @@
 //! Traits for all things that have to do with storing and retrieving entities.
 
 use crate::entity::StoreItem;
+use crate::error::{ErrorKind, TapError};
 
-use std::error::Error;
 use std::fmt::Debug;
 use std::future::Future;
 use std::pin::Pin;
 
-use crate::QuadQuery;
+use crate::{QuadQuery, Query};
 
-pub type StoreError = Box<dyn Error + Send + Sync + 'static>;
+/// Errors returned by stores. Store implementations usually create these
+/// using `TapError::store`.
+pub type StoreError = TapError;
 
 /// An entity store, storing JSON-LD `Entity` objects.
 #[async_trait::async_trait]
@@ -29,6 +31,30 @@ pub trait EntityStore: Debug + Send {
     /// The array elements are in numeric order of the placeholders.
     async fn query(&mut self, query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError>;
 
+    /// Runs a `Query`, which may contain filters, optional and negated patterns, ordering, and
+    /// paging. Placeholders that are only bound by optional patterns may be `None`.
+    ///
+    /// The default implementation only supports basic queries, by passing them to `query`. Stores
+    /// that can't run the other queries natively can use `evaluate_query`.
+    async fn select(&mut self, query: Query) -> Result<Vec<Vec<Option<String>>>, StoreError> {
+        if !query.is_basic() {
+            return Err(TapError::new(
+                ErrorKind::Store,
+                "this store only supports queries without filters, optional patterns or ordering",
+            ));
+        }
+
+        let (offset, limit) = (query.offset, query.limit.unwrap_or(usize::MAX));
+        let rows = self.query(query.patterns).await?;
+
+        Ok(rows
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|row| row.into_iter().map(Some).collect())
+            .collect())
+    }
+
     /// Reads N amount of items from the collection corresponding to a specific ID. If a cursor is passed,
     /// it can be used to paginate.
     async fn read_collection(
@@ -56,6 +82,22 @@ pub trait EntityStore: Debug + Send {
 
     /// Removes an item from the collection.
     async fn remove_collection(&mut self, path: String, item: String) -> Result<(), StoreError>;
+
+    /// Starts a transaction, so all following changes can be committed or rolled back at once.
+    /// Returns false if this store doesn't support transactions, in which case nothing happens.
+    async fn begin_transaction(&mut self) -> Result<bool, StoreError> {
+        Ok(false)
+    }
+
+    /// Commits all changes made since the transaction was started.
+    async fn commit_transaction(&mut self) -> Result<(), StoreError> {
+        Ok(())
+    }
+
+    /// Discards all changes made since the transaction was started.
+    async fn rollback_transaction(&mut self) -> Result<(), StoreError> {
+        Ok(())
+    }
 }
 
 // Manually implement async_trait to save an indirection; this will hopefully be inlined.
@@ -92,6 +134,16 @@ impl EntityStore for &mut dyn EntityStore {
         (**self).query(query)
     }
 
+    fn select<'a: 'res, 'res>(
+        &'a mut self,
+        query: Query,
+    ) -> Pin<Box<dyn Future<Output = Result<Vec<Vec<Option<String>>>, StoreError>> + Send + 'res>>
+    where
+        Self: 'res,
+    {
+        (**self).select(query)
+    }
+
     fn read_collection<'a: 'res, 'res>(
         &'a mut self,
         path: String,
@@ -146,8 +198,38 @@ impl EntityStore for &mut dyn EntityStore {
     {
         (**self).remove_collection(path, item)
     }
+
+    fn begin_transaction<'a: 'res, 'res>(
+        &'a mut self,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, StoreError>> + Send + 'res>>
+    where
+        Self: 'res,
+    {
+        (**self).begin_transaction()
+    }
+
+    fn commit_transaction<'a: 'res, 'res>(
+        &'a mut self,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + 'res>>
+    where
+        Self: 'res,
+    {
+        (**self).commit_transaction()
+    }
+
+    fn rollback_transaction<'a: 'res, 'res>(
+        &'a mut self,
+    ) -> Pin<Box<dyn Future<Output = Result<(), StoreError>> + Send + 'res>>
+    where
+        Self: 'res,
+    {
+        (**self).rollback_transaction()
+    }
 }
 
+/// A page of a collection. `after` and `before` are the cursors of the pages following and preceding
+/// it, and `count` the total amount of items in the collection, if known. Cursors are used in URLs, so
+/// they shouldn't contain `&` or whitespace.
 #[derive(Debug)]
 pub struct CollectionPointer {
     pub items: Vec<String>,
@@ -0,0 +1,143 @@
+//! The error type shared by handlers, stores, and everything else in kroeg-tap.
+
+use std::error::Error;
+use std::fmt;
+
+/// The kind of an error, describing whose fault it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The request is malformed or incomplete.
+    InvalidInput,
+
+    /// The user may not do what the request asks for.
+    Unauthorized,
+
+    /// Something the request refers to doesn't exist.
+    NotFound,
+
+    /// The request conflicts with the current state, e.g. a duplicate vote.
+    Conflict,
+
+    /// The entity or queue store failed.
+    Store,
+
+    /// Retrieving something from another server failed.
+    Remote,
+
+    /// The message was already handled before, so nothing more has to be
+    ///  done. A `Pipeline` stops at this error, discards the changes made so
+    ///  far, and reports success.
+    AlreadyHandled,
+}
+
+impl ErrorKind {
+    /// The HTTP status code that best describes this kind of error.
+    pub fn http_status(self) -> u16 {
+        match self {
+            ErrorKind::InvalidInput => 400,
+            ErrorKind::Unauthorized => 403,
+            ErrorKind::NotFound => 404,
+            ErrorKind::Conflict => 409,
+            ErrorKind::Store => 500,
+            ErrorKind::Remote => 502,
+            ErrorKind::AlreadyHandled => 202,
+        }
+    }
+}
+
+/// An error returned by a handler, store, or authorizer. It wraps the
+///  original error, which can be retrieved using `downcast`.
+#[derive(Debug)]
+pub struct TapError {
+    kind: ErrorKind,
+    error: Box<dyn Error + Send + Sync + 'static>,
+}
+
+impl TapError {
+    pub fn new<E>(kind: ErrorKind, error: E) -> TapError
+    where
+        E: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        TapError {
+            kind,
+            error: error.into(),
+        }
+    }
+
+    /// Wraps an error of a store implementation.
+    pub fn store<E>(error: E) -> TapError
+    where
+        E: Into<Box<dyn Error + Send + Sync + 'static>>,
+    {
+        TapError::new(ErrorKind::Store, error)
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The HTTP status code that best describes this error.
+    pub fn http_status(&self) -> u16 {
+        self.kind.http_status()
+    }
+
+    /// Returns the wrapped error.
+    pub fn get_ref(&self) -> &(dyn Error + Send + Sync + 'static) {
+        &*self.error
+    }
+
+    /// Returns the wrapped error, dropping the kind.
+    pub fn into_inner(self) -> Box<dyn Error + Send + Sync + 'static> {
+        self.error
+    }
+
+    /// Attempts to downcast the wrapped error to a concrete type. On failure,
+    ///  the `TapError` is returned unchanged.
+    pub fn downcast<T: Error + 'static>(self) -> Result<Box<T>, TapError> {
+        let kind = self.kind;
+
+        self.error
+            .downcast()
+            .map_err(|error| TapError { kind, error })
+    }
+
+    /// Returns a reference to the wrapped error, if it is of this type.
+    pub fn downcast_ref<T: Error + 'static>(&self) -> Option<&T> {
+        self.error.downcast_ref()
+    }
+
+    /// Returns true if the wrapped error is of this type.
+    pub fn is<T: Error + 'static>(&self) -> bool {
+        self.error.is::<T>()
+    }
+}
+
+impl fmt::Display for TapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl Error for TapError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl From<Box<dyn Error + Send + Sync + 'static>> for TapError {
+    fn from(error: Box<dyn Error + Send + Sync + 'static>) -> TapError {
+        TapError::store(error)
+    }
+}
+
+impl From<&str> for TapError {
+    fn from(error: &str) -> TapError {
+        TapError::store(error)
+    }
+}
+
+impl From<String> for TapError {
+    fn from(error: String) -> TapError {
+        TapError::store(error)
+    }
+}
@@ -0,0 +1,436 @@
+//! A reference evaluator for `Query`, which stores can use to answer queries
+//!  over data they can't query natively.
+
+use chrono::{DateTime, Utc};
+use jsonld::nodemap::Pointer;
+use serde_json::Value as JValue;
+use std::cmp::Ordering;
+
+use crate::entity::StoreItem;
+use crate::query::{Filter, QuadQuery, Query, QueryId, QueryObject};
+use crate::querybuilder::{Placeholder, RDF_TYPE};
+
+/// The object of a `Quad`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Id(String),
+    Literal {
+        value: String,
+        type_id: Option<String>,
+        language: Option<String>,
+    },
+}
+
+impl Term {
+    /// The ID, or the value of the literal.
+    pub fn value(&self) -> &str {
+        match self {
+            Term::Id(id) => id,
+            Term::Literal { value, .. } => value,
+        }
+    }
+}
+
+/// A single statement, as used by `evaluate_query`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quad {
+    pub subject: String,
+    pub predicate: String,
+    pub object: Term,
+}
+
+fn push_pointer(quads: &mut Vec<Quad>, subject: &str, predicate: &str, pointer: &Pointer) {
+    let object = match pointer {
+        Pointer::Id(id) => Term::Id(id.to_owned()),
+        Pointer::Value(value) => Term::Literal {
+            value: match &value.value {
+                JValue::String(string) => string.to_owned(),
+                value => value.to_string(),
+            },
+            type_id: value.type_id.clone(),
+            language: value.language.clone(),
+        },
+        Pointer::List(list) => {
+            for pointer in list {
+                push_pointer(quads, subject, predicate, pointer);
+            }
+
+            return;
+        }
+    };
+
+    quads.push(Quad {
+        subject: subject.to_owned(),
+        predicate: predicate.to_owned(),
+        object,
+    });
+}
+
+/// Translates the entities in a `StoreItem` to quads, excluding the meta
+///  entity. Types are translated to `rdf:type`, and lists are flattened.
+pub fn item_quads(item: &StoreItem) -> Vec<Quad> {
+    let mut quads = Vec::new();
+
+    for (id, entity) in &item.data {
+        if id == kroeg!(meta) {
+            continue;
+        }
+
+        for typ in &entity.types {
+            quads.push(Quad {
+                subject: id.to_owned(),
+                predicate: RDF_TYPE.to_owned(),
+                object: Term::Id(typ.to_owned()),
+            });
+        }
+
+        for (predicate, values) in entity.iter() {
+            for pointer in values {
+                push_pointer(&mut quads, id, predicate, pointer);
+            }
+        }
+    }
+
+    quads
+}
+
+type Row = Vec<Option<Term>>;
+
+fn bind_id(row: &mut Row, id: &QueryId, term: &Term) -> bool {
+    match (id, term) {
+        (QueryId::Ignore, _) => true,
+        (QueryId::Value(value), Term::Id(id)) => value == id,
+        (QueryId::Any(values), Term::Id(id)) => values.contains(id),
+        (QueryId::Placeholder(i), term) => match &row[*i as usize] {
+            Some(bound) => bound == term,
+            None => {
+                row[*i as usize] = Some(term.clone());
+                true
+            }
+        },
+        _ => false,
+    }
+}
+
+fn bind_object(row: &mut Row, object: &QueryObject, term: &Term) -> bool {
+    match (object, term) {
+        (QueryObject::Id(id), term) => bind_id(row, id, term),
+        (
+            QueryObject::Object { value, type_id },
+            Term::Literal {
+                value: term_value,
+                type_id: term_type,
+                ..
+            },
+        ) => {
+            value == term_value
+                && match term_type {
+                    Some(term_type) => bind_id(row, type_id, &Term::Id(term_type.to_owned())),
+                    None => type_id == &QueryId::Ignore,
+                }
+        }
+        (
+            QueryObject::LanguageString { value, language },
+            Term::Literal {
+                value: term_value,
+                language: Some(term_language),
+                ..
+            },
+        ) => value == term_value && language.eq_ignore_ascii_case(term_language),
+        _ => false,
+    }
+}
+
+/// Finds all the ways the rows can be extended to match every pattern.
+fn solve(patterns: &[QuadQuery], quads: &[Quad], rows: Vec<Row>) -> Vec<Row> {
+    patterns
+        .iter()
+        .fold(rows, |rows, QuadQuery(subject, predicate, object)| {
+            let mut result = Vec::new();
+            for row in &rows {
+                for quad in quads {
+                    let mut row = row.clone();
+                    if bind_id(&mut row, subject, &Term::Id(quad.subject.to_owned()))
+                        && bind_id(&mut row, predicate, &Term::Id(quad.predicate.to_owned()))
+                        && bind_object(&mut row, object, &quad.object)
+                    {
+                        result.push(row);
+                    }
+                }
+            }
+
+            result
+        })
+}
+
+fn parse_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|f| f.with_timezone(&Utc))
+}
+
+fn matches_filter(row: &Row, filter: &Filter) -> bool {
+    let term = |placeholder: &Placeholder| row.get(placeholder.index()).and_then(|f| f.as_ref());
+
+    match filter {
+        Filter::Number(placeholder, comparison, number) => term(placeholder)
+            .and_then(|f| f.value().parse::<f64>().ok())
+            .and_then(|f| f.partial_cmp(number))
+            .map(|f| comparison.matches(f))
+            .unwrap_or(false),
+
+        Filter::Date(placeholder, comparison, date) => term(placeholder)
+            .and_then(|f| parse_date(f.value()))
+            .map(|f| comparison.matches(f.cmp(date)))
+            .unwrap_or(false),
+
+        Filter::StartsWith(placeholder, prefix) => term(placeholder)
+            .map(|f| f.value().starts_with(prefix as &str))
+            .unwrap_or(false),
+
+        Filter::Language(placeholder, language) => match term(placeholder) {
+            Some(Term::Literal {
+                language: Some(term_language),
+                ..
+            }) => {
+                let term_language = term_language.to_lowercase();
+                let language = language.to_lowercase();
+
+                language == "*"
+                    || term_language == language
+                    || term_language.starts_with(&format!("{}-", language))
+            }
+            _ => false,
+        },
+    }
+}
+
+/// How the values of a placeholder are compared when sorting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SortKind {
+    Number,
+    Date,
+    Text,
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    value.parse::<f64>().ok().filter(|f| !f.is_nan())
+}
+
+/// Decides how to compare the values bound to a placeholder: numerically if
+///  all of them are numbers, by date if all of them are dates, and as strings
+///  otherwise. Deciding this once for all rows keeps the order total.
+fn sort_kind(rows: &[Row], index: usize) -> SortKind {
+    let mut values = rows
+        .iter()
+        .filter_map(|f| f.get(index).and_then(|f| f.as_ref()))
+        .map(Term::value);
+
+    if values.clone().all(|f| parse_number(f).is_some()) {
+        SortKind::Number
+    } else if values.all(|f| parse_date(f).is_some()) {
+        SortKind::Date
+    } else {
+        SortKind::Text
+    }
+}
+
+/// Compares two values for sorting, as decided by `sort_kind`. Unbound values
+///  come first.
+fn compare_terms(kind: SortKind, a: &Option<Term>, b: &Option<Term>) -> Ordering {
+    let (a, b) = match (a, b) {
+        (Some(a), Some(b)) => (a.value(), b.value()),
+        (a, b) => return a.is_some().cmp(&b.is_some()),
+    };
+
+    match kind {
+        SortKind::Number => parse_number(a)
+            .partial_cmp(&parse_number(b))
+            .unwrap_or(Ordering::Equal),
+        SortKind::Date => parse_date(a).cmp(&parse_date(b)),
+        SortKind::Text => a.cmp(b),
+    }
+}
+
+/// Evaluates a query over a set of quads. Every row contains the value of
+///  each placeholder, in numeric order, or `None` if a placeholder is only
+///  used in an optional group that didn't match.
+pub fn evaluate_query(query: &Query, quads: &[Quad]) -> Vec<Vec<Option<String>>> {
+    let width = query.width();
+    let mut rows = solve(&query.patterns, quads, vec![vec![None; width]]);
+
+    for group in &query.optional {
+        rows = rows
+            .into_iter()
+            .flat_map(|row| {
+                let extended = solve(group, quads, vec![row.clone()]);
+                if extended.is_empty() {
+                    vec![row]
+                } else {
+                    extended
+                }
+            })
+            .collect();
+    }
+
+    for group in &query.not {
+        rows.retain(|row| {
+            // Placeholders only used in this group need room to be bound.
+            let mut row = row.clone();
+            row.resize(width.max(Query::new(group.clone()).width()), None);
+
+            solve(group, quads, vec![row]).is_empty()
+        });
+    }
+
+    rows.retain(|row| query.filters.iter().all(|f| matches_filter(row, f)));
+
+    let kinds: Vec<_> = query
+        .order
+        .iter()
+        .map(|f| sort_kind(&rows, f.placeholder.index()))
+        .collect();
+
+    rows.sort_by(|a, b| {
+        for (order, kind) in query.order.iter().zip(&kinds) {
+            let index = order.placeholder.index();
+            let ordering = compare_terms(
+                *kind,
+                a.get(index).unwrap_or(&None),
+                b.get(index).unwrap_or(&None),
+            );
+            let ordering = if order.descending {
+                ordering.reverse()
+            } else {
+                ordering
+            };
+
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+
+        Ordering::Equal
+    });
+
+    rows.into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .map(|row| {
+            row.into_iter()
+                .map(|f| f.map(|f| f.value().to_owned()))
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{evaluate_query, Quad, Term};
+    use crate::query::{Comparison, Filter, QuadQuery, Query, QueryId};
+    use crate::querybuilder::{Placeholder, QueryBuilder};
+
+    fn quad(subject: &str, predicate: &str, object: Term) -> Quad {
+        Quad {
+            subject: subject.to_owned(),
+            predicate: predicate.to_owned(),
+            object,
+        }
+    }
+
+    fn literal(value: &str) -> Term {
+        Term::Literal {
+            value: value.to_owned(),
+            type_id: None,
+            language: None,
+        }
+    }
+
+    fn quads() -> Vec<Quad> {
+        vec![
+            quad("/a", as2!(published), literal("2019-01-02T00:00:00Z")),
+            quad("/a", as2!(name), literal("first")),
+            quad("/b", as2!(published), literal("2019-03-04T00:00:00Z")),
+            quad("/c", as2!(published), literal("2019-05-06T00:00:00Z")),
+            quad("/c", as2!(name), literal("third")),
+        ]
+    }
+
+    fn published() -> Vec<QuadQuery> {
+        let mut query = QueryBuilder::new();
+        let (object, published) = (query.placeholder(), query.placeholder());
+        query.quad(object, as2!(published), published);
+
+        query.build()
+    }
+
+    #[test]
+    fn optional_leaves_unbound() {
+        let query = Query::new(published())
+            .with_optional(vec![QuadQuery(
+                QueryId::Placeholder(0),
+                as2!(name).into(),
+                Placeholder::new(2).into(),
+            )])
+            .order_by(Placeholder::new(0), false);
+
+        let rows = evaluate_query(&query, &quads());
+        let names: Vec<_> = rows.iter().map(|f| f[2].as_deref()).collect();
+
+        assert_eq!(names, vec![Some("first"), None, Some("third")]);
+    }
+
+    #[test]
+    fn filters_orders_and_limits() {
+        let query = Query::new(published())
+            .with_filter(Filter::Date(
+                Placeholder::new(1),
+                Comparison::Greater,
+                "2019-02-01T00:00:00Z".parse().unwrap(),
+            ))
+            .order_by(Placeholder::new(1), true)
+            .with_limit(1);
+
+        let rows = evaluate_query(&query, &quads());
+
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][0].as_deref(), Some("/c"));
+    }
+
+    #[test]
+    fn orders_mixed_values_as_strings() {
+        let quads = vec![
+            quad("/a", as2!(name), literal("10")),
+            quad("/b", as2!(name), literal("9")),
+            quad("/c", as2!(name), literal("2019-01-02T00:00:00Z")),
+            quad("/d", as2!(name), literal("abc")),
+        ];
+        let mut builder = QueryBuilder::new();
+        let (object, name) = (builder.placeholder(), builder.placeholder());
+        builder.quad(object, as2!(name), name);
+        let query = Query::new(builder.build());
+
+        let order = |query: Query| -> Vec<_> {
+            evaluate_query(&query, &quads)
+                .into_iter()
+                .map(|f| f[0].clone().unwrap())
+                .collect()
+        };
+
+        assert_eq!(
+            order(query.clone().order_by(Placeholder::new(1), false)),
+            vec!["/a", "/c", "/b", "/d"]
+        );
+
+        // Without the non-numeric values, numbers are compared as numbers.
+        let numbers = query
+            .clone()
+            .with_filter(Filter::Number(
+                Placeholder::new(1),
+                Comparison::Greater,
+                0.0,
+            ))
+            .order_by(Placeholder::new(1), false);
+        assert_eq!(order(numbers), vec!["/b", "/a"]);
+    }
+}
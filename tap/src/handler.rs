@@ -1,7 +1,7 @@
+use crate::entity::StoreItem;
+use crate::error::TapError;
 use crate::user::Context;
 
-use std::error::Error;
-
 /// Handler used to process incoming ActivityPub messages.
 #[async_trait::async_trait]
 pub trait MessageHandler: Send + Sync {
@@ -11,5 +11,59 @@ pub trait MessageHandler: Send + Sync {
         context: &mut Context<'_, '_>,
         inbox: &mut String,
         id: &mut String,
-    ) -> Result<(), Box<dyn Error + Send + Sync + 'static>>;
+    ) -> Result<(), TapError>;
+}
+
+/// Handler used to process incoming activities of specific types. Unlike a
+///  `MessageHandler`, it receives the activity already loaded, and can't
+///  change the inbox or ID of the message.
+///
+/// Every `ActivityHandler` is also a `MessageHandler`, which loads the activity
+///  itself. Inside a `Pipeline`, the activity is loaded only once and shared
+///  between all activity handlers.
+#[async_trait::async_trait]
+pub trait ActivityHandler: Send + Sync {
+    /// The types of activity this handler applies to. If empty, the handler
+    ///  is called for every activity.
+    fn applies_to(&self) -> &[&str];
+
+    /// Process a single activity, which is of one of the types this handler
+    ///  applies to.
+    async fn handle_activity(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &str,
+        activity: &StoreItem,
+    ) -> Result<(), TapError>;
+}
+
+/// Checks if an activity is of any of the types a handler applies to.
+pub fn handler_applies(applies_to: &[&str], activity: &StoreItem) -> bool {
+    applies_to.is_empty()
+        || activity
+            .main()
+            .types
+            .iter()
+            .any(|f| applies_to.contains(&(f as &str)))
+}
+
+#[async_trait::async_trait]
+impl<T: ActivityHandler> MessageHandler for T {
+    async fn handle(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &mut String,
+        id: &mut String,
+    ) -> Result<(), TapError> {
+        let activity = match context.entity_store.get(id.to_owned(), false).await? {
+            Some(activity) => activity,
+            None => return Ok(()),
+        };
+
+        if !handler_applies(self.applies_to(), &activity) {
+            return Ok(());
+        }
+
+        self.handle_activity(context, inbox, &activity).await
+    }
 }
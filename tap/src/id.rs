@@ -5,9 +5,12 @@ use std::collections::{BTreeMap, HashMap, HashSet};
 
 use crate::entity::StoreItem;
 use crate::entitystore::StoreError;
+use crate::error::{ErrorKind, TapError};
+use crate::idstrategy::IdRequest;
+use crate::slug::{is_reserved_slug, slugify};
 use crate::user::Context;
 
-const ALPHABET: [char; 32] = [
+pub(crate) const ALPHABET: [char; 32] = [
     'y', 'b', 'n', 'd', 'r', 'f', 'g', '8', 'e', 'j', 'k', 'm', 'c', 'p', 'q', 'x', 'o', 't', '1',
     'u', 'w', 'i', 's', 'z', 'a', '3', '4', '5', 'h', '7', '6', '9',
 ];
@@ -15,60 +18,66 @@ const ALPHABET: [char; 32] = [
 /// Generates a random suggestion for en entity to use when the suggested
 ///  name is not available anymore.
 pub fn get_suggestion(depth: u32) -> String {
-    let mut data: [u8; 8] = [0; 8];
+    random_suggestion(depth, 0)
+}
 
-    thread_rng().fill(&mut data);
+/// Generates a random suggestion, which grows longer with every failed
+///  attempt so the chance of another collision keeps shrinking.
+pub(crate) fn random_suggestion(depth: u32, attempt: usize) -> String {
+    let length = if depth == 0 { 8 } else { 4 } + attempt * 2;
+    let mut rng = thread_rng();
 
-    let data: String = data
-        .iter()
-        .map(|f| ALPHABET[(*f & 0b11111) as usize])
+    let data: String = (0..length)
+        .map(|_| ALPHABET[rng.gen_range(0, ALPHABET.len())])
         .collect();
 
     if depth == 0 {
         format!("{}-{}", &data[..4], &data[4..])
     } else {
-        format!("{}", &data[..4])
+        data
     }
 }
 
-const NAMES: [&'static str; 1] = [as2!(preferredUsername)];
-
-fn translate_name(predicate: &str, nam: &str) -> String {
-    let mut result = String::new();
+/// The longest slug generated from a username.
+const MAX_USERNAME_SLUG: usize = 15;
 
-    if predicate == as2!(preferredUsername) {
-        result += "~";
-    }
+/// The longest slug generated from the name or summary of a Note.
+const MAX_NOTE_SLUG: usize = 32;
 
-    for ch in nam.chars().take(15) {
-        if ch.is_alphanumeric() {
-            result += &ch.to_lowercase().collect::<String>();
-        } else {
-            result += "-";
-        }
+fn first_string<'a>(main: &'a Entity, predicate: &str) -> Option<&'a str> {
+    match main[predicate].first() {
+        Some(Pointer::Value(Value {
+            value: JValue::String(string),
+            ..
+        })) => Some(string.as_str()),
+        _ => None,
     }
-
-    result
 }
 
 /// Generates a suggestion for a short name in the URL of an entity.
+///
+/// Actors are named after their `as:preferredUsername` (prefixed with `~`),
+///  and Notes after their `as:name` or `as:summary`. Other objects without
+///  an actor are named after their type. Reserved slugs are never suggested.
 pub fn shortname_suggestion(main: &Entity) -> Option<String> {
-    for name in NAMES.iter() {
-        if main[name].len() > 0 {
-            let first = &main[name][0];
-            if let Pointer::Value(ref val) = first {
-                if let JValue::String(ref string) = val.value {
-                    return Some(translate_name(name, string));
-                }
+    if let Some(username) = first_string(main, as2!(preferredUsername)) {
+        if let Some(slug) = slugify(username, MAX_USERNAME_SLUG) {
+            return Some(format!("~{}", slug));
+        }
+    }
+
+    if main.types.iter().any(|f| f == as2!(Note)) {
+        for predicate in &[as2!(name), as2!(summary)] {
+            match first_string(main, predicate).and_then(|f| slugify(f, MAX_NOTE_SLUG)) {
+                Some(slug) if !is_reserved_slug(&slug) => return Some(slug),
+                _ => {}
             }
         }
     }
 
     if main.types.len() > 0 && main[as2!(actor)].len() == 0 {
-        return Some(translate_name(
-            "@type",
-            main.types[0].split('#').last().unwrap(),
-        ));
+        let typename = main.types[0].split('#').last().unwrap();
+        return slugify(typename, MAX_USERNAME_SLUG).filter(|f| !is_reserved_slug(f));
     }
 
     None
@@ -181,38 +190,45 @@ pub async fn assign_ids(
     Ok(root.and_then(|f| remap.get(&f).cloned()))
 }
 
+/// The amount of candidate IDs tried before giving up on assigning an ID.
+///  Every retry of the default strategy is two characters longer, so eight
+///  collisions in a row won't happen by chance; hitting this limit means the
+///  store claims every ID is taken, and looping on would never end.
+const MAX_ID_ATTEMPTS: usize = 8;
+
+pub(crate) fn join_id(parent: &str, suggestion: &str) -> String {
+    format!(
+        "{}{}{}",
+        parent,
+        if parent.ends_with("/") { "" } else { "/" },
+        suggestion
+    )
+}
+
 /// Finds a valid unused ID for an entity, based on an arbitrary
 ///  suggestion, the parent ID of the entity, and the amount of ancestors
-///  of this entitiy.
+///  of this entitiy. The shape of the ID is decided by the `IdStrategy` of
+///  the context.
+///
+/// Fails with a `Conflict` error if no unused ID was found within
+///  `MAX_ID_ATTEMPTS` candidates.
 pub async fn assign_id(
     context: &mut Context<'_, '_>,
     suggestion: Option<String>,
     parent: Option<String>,
     depth: u32,
 ) -> Result<String, StoreError> {
-    let parent = parent.unwrap_or(context.server_base.to_owned());
-    let suggestion = suggestion.unwrap_or(get_suggestion(depth));
-
-    let preliminary = format!(
-        "{}{}{}",
-        parent,
-        if parent.ends_with("/") { "" } else { "/" },
-        suggestion
-    );
-
-    let test = context.entity_store.get(preliminary.clone(), false).await?;
-    if test.is_none() {
-        return Ok(preliminary);
-    }
-
-    for _ in 1isize..3isize {
-        let suggestion = get_suggestion(depth);
-        let preliminary = format!(
-            "{}{}{}",
-            parent,
-            if parent.ends_with("/") { "" } else { "/" },
-            suggestion
-        );
+    let strategy = context.id_strategy.clone();
+    let server_base = context.server_base.to_owned();
+    let request = IdRequest {
+        server_base: &server_base,
+        suggestion: suggestion.as_ref().map(|f| f as &str),
+        parent: parent.as_ref().map(|f| f as &str),
+        depth,
+    };
+
+    for attempt in 0..MAX_ID_ATTEMPTS {
+        let preliminary = strategy.candidate(&request, attempt);
         let test = context
             .entity_store
             .get(preliminary.to_owned(), false)
@@ -223,5 +239,11 @@ pub async fn assign_id(
         }
     }
 
-    panic!("TODO: better handling of ID assignment");
+    Err(TapError::new(
+        ErrorKind::Conflict,
+        format!(
+            "could not find an unused ID below {}",
+            parent.as_ref().unwrap_or(&server_base)
+        ),
+    ))
 }
@@ -0,0 +1,157 @@
+use chrono::Utc;
+use rand::{thread_rng, Rng};
+use std::fmt::Debug;
+
+use crate::id::{join_id, random_suggestion, ALPHABET};
+
+/// The information an `IdStrategy` has available to build a new ID.
+#[derive(Debug, Clone, Copy)]
+pub struct IdRequest<'a> {
+    /// The base URI of the server, e.g. `https://example.com`
+    pub server_base: &'a str,
+
+    /// A human-readable name suggested for the entity, e.g. `~puck` or
+    ///  `likes`.
+    pub suggestion: Option<&'a str>,
+
+    /// The ID of the entity this entity belongs to, if any.
+    pub parent: Option<&'a str>,
+
+    /// The amount of ancestors of this entity.
+    pub depth: u32,
+}
+
+/// Decides what the IDs of newly created entities look like.
+///
+/// `assign_id` asks the strategy for a candidate ID, and checks if it is still
+///  available in the entity store. If it isn't, the strategy is asked again,
+///  with an increased `attempt`.
+pub trait IdStrategy: Debug + Send + Sync {
+    /// Builds a candidate ID for a new entity. `attempt` starts at 0, and is
+    ///  increased every time the previous candidate was already taken.
+    fn candidate(&self, request: &IdRequest, attempt: usize) -> String;
+}
+
+/// The default strategy. IDs are nested below their parent, and use the
+///  suggested name if there is one, e.g. `https://example.com/~puck/inbox`.
+///  Otherwise, or when the suggested name is taken, a short random name is
+///  used.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HierarchicalIds;
+
+impl IdStrategy for HierarchicalIds {
+    fn candidate(&self, request: &IdRequest, attempt: usize) -> String {
+        let parent = request.parent.unwrap_or(request.server_base);
+
+        match (request.suggestion, attempt) {
+            (Some(suggestion), 0) => join_id(parent, suggestion),
+            (Some(_), attempt) => join_id(parent, &random_suggestion(request.depth, attempt - 1)),
+            (None, attempt) => join_id(parent, &random_suggestion(request.depth, attempt)),
+        }
+    }
+}
+
+/// The way generated IDs are turned into a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdGenerator {
+    /// A random string, using the same alphabet as `HierarchicalIds`.
+    Random,
+
+    /// A random (version 4) UUID, e.g. `0b6e9c1a-62f3-4c0e-9d2b-6f4e1f0a7c55`.
+    Uuid,
+
+    /// A ULID-style ID, which starts with the current time in milliseconds.
+    ///  These sort in the order they were created in.
+    TimeOrdered,
+}
+
+impl IdGenerator {
+    /// Generates a new name.
+    pub fn generate(self) -> String {
+        match self {
+            IdGenerator::Random => {
+                let mut rng = thread_rng();
+                (0..16)
+                    .map(|_| ALPHABET[rng.gen_range(0, ALPHABET.len())])
+                    .collect()
+            }
+
+            IdGenerator::Uuid => uuid_v4(),
+            IdGenerator::TimeOrdered => time_ordered(),
+        }
+    }
+}
+
+/// Where generated IDs are placed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdLayout {
+    /// Below the parent of the entity, e.g. `https://example.com/~puck/{id}`.
+    Nested,
+
+    /// In a single flat namespace, e.g. `https://example.com/objects/{id}`.
+    Flat,
+}
+
+/// Strategy that ignores suggested names, and generates every ID instead.
+#[derive(Debug, Clone, Copy)]
+pub struct GeneratedIds {
+    pub generator: IdGenerator,
+    pub layout: IdLayout,
+}
+
+impl GeneratedIds {
+    pub fn new(generator: IdGenerator, layout: IdLayout) -> GeneratedIds {
+        GeneratedIds { generator, layout }
+    }
+}
+
+impl IdStrategy for GeneratedIds {
+    fn candidate(&self, request: &IdRequest, _attempt: usize) -> String {
+        let name = self.generator.generate();
+
+        match self.layout {
+            IdLayout::Nested => join_id(request.parent.unwrap_or(request.server_base), &name),
+            IdLayout::Flat => join_id(&join_id(request.server_base, "objects"), &name),
+        }
+    }
+}
+
+fn uuid_v4() -> String {
+    let mut data: [u8; 16] = [0; 16];
+    thread_rng().fill(&mut data);
+
+    // version 4, variant 1
+    data[6] = (data[6] & 0x0f) | 0x40;
+    data[8] = (data[8] & 0x3f) | 0x80;
+
+    let hex: String = data.iter().map(|f| format!("{:02x}", f)).collect();
+
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+// Crockford's base32, lowercased to match the other IDs.
+const CROCKFORD: [char; 32] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'j',
+    'k', 'm', 'n', 'p', 'q', 'r', 's', 't', 'v', 'w', 'x', 'y', 'z',
+];
+
+fn time_ordered() -> String {
+    let mut rng = thread_rng();
+
+    // 48 bits of milliseconds, followed by 80 random bits.
+    let time = (Utc::now().timestamp_millis() as u64 & 0xffff_ffff_ffff) as u128;
+    let random = ((rng.gen::<u64>() as u128) << 16) | (rng.gen::<u16>() as u128);
+    let value = (time << 80) | random;
+
+    (0..26)
+        .rev()
+        .map(|i| CROCKFORD[((value >> (i * 5)) & 0b11111) as usize])
+        .collect()
+}
@@ -1,34 +1,89 @@
 #![feature(never_type)]
 
 /// Macro for translating short as:Note style IDs to full strings as used in
-/// `Entity`. e.g. `as2!(name)`.
+/// `Entity`. e.g. `as2!(name)`. Without arguments, e.g. `as2!()`, these
+/// macros return the namespace itself.
 #[macro_export]
 macro_rules! as2 {
+    () => {
+        "https://www.w3.org/ns/activitystreams#"
+    };
     ($ident:ident) => {
         concat!("https://www.w3.org/ns/activitystreams#", stringify!($ident))
     };
 }
 #[macro_export]
 macro_rules! ldp {
+    () => {
+        "http://www.w3.org/ns/ldp#"
+    };
     ($ident:ident) => {
         concat!("http://www.w3.org/ns/ldp#", stringify!($ident))
     };
 }
 #[macro_export]
 macro_rules! kroeg {
+    () => {
+        "https://puckipedia.com/kroeg/ns#"
+    };
     ($ident:ident) => {
         concat!("https://puckipedia.com/kroeg/ns#", stringify!($ident))
     };
 }
 #[macro_export]
 macro_rules! sec {
+    () => {
+        "https://w3id.org/security#"
+    };
     ($ident:ident) => {
         concat!("https://w3id.org/security#", stringify!($ident))
     };
 }
+#[macro_export]
+macro_rules! toot {
+    () => {
+        "http://joinmastodon.org/ns#"
+    };
+    ($ident:ident) => {
+        concat!("http://joinmastodon.org/ns#", stringify!($ident))
+    };
+}
+#[macro_export]
+macro_rules! litepub {
+    () => {
+        "http://litepub.social/ns#"
+    };
+    ($ident:ident) => {
+        concat!("http://litepub.social/ns#", stringify!($ident))
+    };
+}
+#[macro_export]
+macro_rules! ostatus {
+    () => {
+        "http://ostatus.org#"
+    };
+    ($ident:ident) => {
+        concat!("http://ostatus.org#", stringify!($ident))
+    };
+}
+#[macro_export]
+macro_rules! vcard {
+    () => {
+        "http://www.w3.org/2006/vcard/ns#"
+    };
+    ($ident:ident) => {
+        concat!("http://www.w3.org/2006/vcard/ns#", stringify!($ident))
+    };
+}
+
+mod error;
+pub use error::*;
 
 mod assemble;
-pub use assemble::{assemble, untangle};
+pub use assemble::{
+    assemble, assemble_with, get_collectionified, untangle, AssembleOptions, EmbedRule,
+    DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE,
+};
 
 mod entity;
 pub use entity::*;
@@ -36,17 +91,47 @@ pub use entity::*;
 mod entitystore;
 pub use entitystore::*;
 
+mod overlay;
+pub use overlay::*;
+
 mod handler;
 pub use handler::*;
 
+mod pipeline;
+pub use pipeline::*;
+
 mod user;
 pub use user::*;
 
 mod id;
 pub use id::*;
 
+mod idstrategy;
+pub use idstrategy::*;
+
+mod slug;
+pub use slug::*;
+
 mod auth;
 pub use auth::*;
 
+mod prefix;
+pub use prefix::*;
+
 mod query;
 pub use query::*;
+
+mod querybuilder;
+pub use querybuilder::*;
+
+mod evaluate;
+pub use evaluate::*;
+
+mod sparql;
+pub use sparql::*;
+
+mod visibility;
+pub use visibility::*;
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,530 @@
+//! Stores that buffer their changes, to apply them all at once later.
+
+use crate::entity::StoreItem;
+use crate::entitystore::{CollectionPointer, EntityStore, QueueItem, QueueStore, StoreError};
+use crate::evaluate::{evaluate_query, item_quads};
+use crate::query::{QuadQuery, Query, QueryId};
+
+use serde_json::json;
+use serde_json::Value as JValue;
+use std::collections::{HashMap, HashSet};
+
+/// A single change buffered by an `OverlayStore`.
+#[derive(Debug, Clone)]
+pub enum Change {
+    /// A `StoreItem` was stored at a path.
+    Put(String, StoreItem),
+
+    /// An item was inserted into a collection: `(collection, item)`.
+    InsertCollection(String, String),
+
+    /// An item was removed from a collection: `(collection, item)`.
+    RemoveCollection(String, String),
+}
+
+impl Change {
+    /// Serializes this change, including the full contents of stored items.
+    pub fn to_json(&self) -> JValue {
+        match self {
+            Change::Put(path, item) => json!({
+                "type": "put",
+                "path": path,
+                "item": item.clone().to_json(),
+            }),
+            Change::InsertCollection(collection, item) => json!({
+                "type": "insert_collection",
+                "collection": collection,
+                "item": item,
+            }),
+            Change::RemoveCollection(collection, item) => json!({
+                "type": "remove_collection",
+                "collection": collection,
+                "item": item,
+            }),
+        }
+    }
+}
+
+/// Everything a pipeline would have done, as recorded during a dry run.
+#[derive(Debug, Clone, Default)]
+pub struct ChangeReport {
+    /// All changes to the entity store, in the order they were made.
+    pub changes: Vec<Change>,
+
+    /// All items added to the queue, as `(event, data)`.
+    pub queued: Vec<(String, String)>,
+}
+
+impl ChangeReport {
+    /// Returns true if nothing would have been changed.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty() && self.queued.is_empty()
+    }
+
+    /// Serializes the report, e.g. to show a preview of an outbox POST.
+    pub fn to_json(&self) -> JValue {
+        let changes: Vec<_> = self.changes.iter().map(Change::to_json).collect();
+        let queued: Vec<_> = self
+            .queued
+            .iter()
+            .map(|(event, data)| json!({ "event": event, "data": data }))
+            .collect();
+
+        json!({ "changes": changes, "queued": queued })
+    }
+}
+
+/// An entity store that keeps all changes made to it in memory, on top of
+///  another store. All reads, including queries, see the buffered changes.
+///
+/// The changes are only written to the underlying store once `commit` is
+///  called. Dropping the overlay discards them.
+#[derive(Debug)]
+pub struct OverlayStore<'a> {
+    inner: &'a mut dyn EntityStore,
+    changes: Vec<Change>,
+}
+
+impl<'a> OverlayStore<'a> {
+    pub fn new(inner: &'a mut dyn EntityStore) -> OverlayStore<'a> {
+        OverlayStore {
+            inner,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Returns true if no changes have been buffered.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// All buffered changes, in the order they were made.
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+
+    /// Discards the overlay, returning the buffered changes without applying them.
+    pub fn into_changes(self) -> Vec<Change> {
+        self.changes
+    }
+
+    /// Writes all buffered changes to the underlying store, in order. If the
+    ///  store fails, the changes before the failing one are not rolled back.
+    pub async fn commit(self) -> Result<(), StoreError> {
+        for change in self.changes {
+            match change {
+                Change::Put(path, mut item) => self.inner.put(path, &mut item).await?,
+                Change::InsertCollection(path, item) => {
+                    self.inner.insert_collection(path, item).await?
+                }
+                Change::RemoveCollection(path, item) => {
+                    self.inner.remove_collection(path, item).await?
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether the item has been inserted into (`Some(true)`) or removed
+    ///  from (`Some(false)`) the collection, if changed at all.
+    fn contains(&self, path: &str, item: &str) -> Option<bool> {
+        self.changes.iter().rev().find_map(|change| match change {
+            Change::InsertCollection(p, i) if p == path && i == item => Some(true),
+            Change::RemoveCollection(p, i) if p == path && i == item => Some(false),
+            _ => None,
+        })
+    }
+
+    /// The latest buffered version of every item that has been put.
+    fn buffered_items(&self) -> HashMap<&str, &StoreItem> {
+        let mut result = HashMap::new();
+        for change in &self.changes {
+            if let Change::Put(path, item) = change {
+                result.insert(path as &str, item);
+            }
+        }
+
+        result
+    }
+
+    /// All items that have been inserted into, or removed from, collections.
+    fn changed_items(&self) -> Vec<(&str, &str)> {
+        let mut result = Vec::new();
+        for change in &self.changes {
+            match change {
+                Change::InsertCollection(path, item) | Change::RemoveCollection(path, item) => {
+                    if !result.contains(&(path as &str, item as &str)) {
+                        result.push((path as &str, item as &str));
+                    }
+                }
+
+                _ => {}
+            }
+        }
+
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> EntityStore for OverlayStore<'a> {
+    async fn get(&mut self, path: String, local: bool) -> Result<Option<StoreItem>, StoreError> {
+        let buffered = self.changes.iter().rev().find_map(|change| match change {
+            Change::Put(p, item) if p == &path => Some(item.clone()),
+            _ => None,
+        });
+
+        match buffered {
+            Some(item) => Ok(Some(item)),
+            None => self.inner.get(path, local).await,
+        }
+    }
+
+    async fn put(&mut self, path: String, item: &mut StoreItem) -> Result<(), StoreError> {
+        self.changes.push(Change::Put(path, item.clone()));
+
+        Ok(())
+    }
+
+    async fn query(&mut self, query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError> {
+        let rows = self.select(Query::new(query)).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|f| f.unwrap_or_default()).collect())
+            .collect())
+    }
+
+    /// The underlying store can't see the buffered items, so if any have been
+    ///  put, the query is evaluated here instead. It is evaluated over the
+    ///  buffered items, and every stored item that has an entity matching a
+    ///  single pattern of the query, which may mean reading a lot of items.
+    async fn select(&mut self, query: Query) -> Result<Vec<Vec<Option<String>>>, StoreError> {
+        if self.buffered_items().is_empty() {
+            return self.inner.select(query).await;
+        }
+
+        let width = query.width().max(Query::new(query.not.concat()).width());
+        let mut subjects = HashSet::new();
+        for QuadQuery(subject, predicate, object) in query
+            .patterns
+            .iter()
+            .chain(query.optional.iter().flatten())
+            .chain(query.not.iter().flatten())
+        {
+            let index = match subject {
+                QueryId::Value(id) => {
+                    subjects.insert(id.to_owned());
+                    continue;
+                }
+                QueryId::Any(ids) => {
+                    subjects.extend(ids.iter().cloned());
+                    continue;
+                }
+                QueryId::Placeholder(index) => *index,
+                QueryId::Ignore => width as u32,
+            };
+
+            let pattern = QuadQuery(
+                QueryId::Placeholder(index),
+                predicate.clone(),
+                object.clone(),
+            );
+            for row in self.inner.select(Query::new(vec![pattern])).await? {
+                if let Some(Some(subject)) = row.into_iter().nth(index as usize) {
+                    subjects.insert(subject);
+                }
+            }
+        }
+
+        // Entities with a fragment ID are stored as part of the item without it.
+        let mut paths = HashSet::new();
+        for subject in subjects {
+            paths.insert(subject.split('#').next().unwrap().to_owned());
+        }
+
+        let mut quads = Vec::new();
+        for path in paths {
+            if self.buffered_items().contains_key(&path as &str) {
+                continue;
+            }
+
+            if let Some(item) = self.inner.get(path, false).await? {
+                quads.extend(item_quads(&item));
+            }
+        }
+
+        for item in self.buffered_items().values() {
+            quads.extend(item_quads(item));
+        }
+
+        Ok(evaluate_query(&query, &quads))
+    }
+
+    /// Buffered insertions show up at the start of the first page, in place of
+    ///  stored items, so pages are never longer than `count`. At least one
+    ///  stored item is kept on the first page, so the following pages carry on
+    ///  from it. The total count includes the buffered changes.
+    async fn read_collection(
+        &mut self,
+        path: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        let mut inserted = Vec::new();
+        if cursor.is_none() {
+            for (p, item) in self.changed_items() {
+                if p == path && self.contains(p, item) == Some(true) {
+                    inserted.insert(0, item.to_owned());
+                }
+            }
+        }
+
+        let count = count.map(|count| {
+            inserted.truncate(count.saturating_sub(1) as usize);
+            count - inserted.len() as u32
+        });
+
+        let mut result = self
+            .inner
+            .read_collection(path.to_owned(), count, cursor)
+            .await?;
+
+        result
+            .items
+            .retain(|item| self.contains(&path, item) != Some(false) && !inserted.contains(item));
+        inserted.append(&mut result.items);
+        result.items = inserted;
+
+        if let Some(total) = result.count {
+            let changed: Vec<_> = self
+                .changed_items()
+                .into_iter()
+                .filter(|(p, _)| *p == path)
+                .map(|(_, item)| (item.to_owned(), self.contains(&path, item) == Some(true)))
+                .collect();
+
+            let mut total = i64::from(total);
+            for (item, inserted) in changed {
+                let stored = !self
+                    .inner
+                    .find_collection(path.to_owned(), item)
+                    .await?
+                    .items
+                    .is_empty();
+
+                if inserted && !stored {
+                    total += 1;
+                } else if !inserted && stored {
+                    total -= 1;
+                }
+            }
+
+            result.count = Some(total.max(0) as u32);
+        }
+
+        Ok(result)
+    }
+
+    async fn find_collection(
+        &mut self,
+        path: String,
+        item: String,
+    ) -> Result<CollectionPointer, StoreError> {
+        match self.contains(&path, &item) {
+            Some(found) => Ok(CollectionPointer {
+                items: if found { vec![item] } else { vec![] },
+                after: None,
+                before: None,
+                count: None,
+            }),
+
+            None => self.inner.find_collection(path, item).await,
+        }
+    }
+
+    async fn insert_collection(&mut self, path: String, item: String) -> Result<(), StoreError> {
+        self.changes.push(Change::InsertCollection(path, item));
+
+        Ok(())
+    }
+
+    async fn read_collection_inverse(
+        &mut self,
+        item: String,
+    ) -> Result<CollectionPointer, StoreError> {
+        let mut result = self.inner.read_collection_inverse(item.to_owned()).await?;
+
+        result
+            .items
+            .retain(|path| self.contains(path, &item) != Some(false));
+
+        for (path, i) in self.changed_items() {
+            if i == item
+                && self.contains(path, i) == Some(true)
+                && !result.items.iter().any(|f| f == path)
+            {
+                result.items.push(path.to_owned());
+            }
+        }
+
+        Ok(result)
+    }
+
+    async fn remove_collection(&mut self, path: String, item: String) -> Result<(), StoreError> {
+        self.changes.push(Change::RemoveCollection(path, item));
+
+        Ok(())
+    }
+}
+
+/// A queue store that keeps all added items, and items marked as succeeded
+///  or failed, in memory until `commit` is called. Getting items is passed to
+///  the underlying queue.
+#[derive(Debug)]
+pub struct BufferedQueue<'a> {
+    inner: &'a mut dyn QueueStore,
+    added: Vec<(String, String)>,
+    marked: Vec<(QueueItem, bool)>,
+}
+
+impl<'a> BufferedQueue<'a> {
+    pub fn new(inner: &'a mut dyn QueueStore) -> BufferedQueue<'a> {
+        BufferedQueue {
+            inner,
+            added: Vec::new(),
+            marked: Vec::new(),
+        }
+    }
+
+    /// All buffered items, as `(event, data)`.
+    pub fn added(&self) -> &[(String, String)] {
+        &self.added
+    }
+
+    /// Discards the queue, returning the buffered items without adding them.
+    ///  Items marked as succeeded or failed are left untouched.
+    pub fn into_added(self) -> Vec<(String, String)> {
+        self.added
+    }
+
+    /// Adds all buffered items to the underlying queue, and then marks the
+    ///  items that succeeded or failed.
+    pub async fn commit(self) -> Result<(), StoreError> {
+        for (event, data) in self.added {
+            self.inner.add(event, data).await?;
+        }
+
+        for (item, success) in self.marked {
+            if success {
+                self.inner.mark_success(item).await?;
+            } else {
+                self.inner.mark_failure(item).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> QueueStore for BufferedQueue<'a> {
+    async fn get_item(&mut self) -> Result<Option<QueueItem>, StoreError> {
+        self.inner.get_item().await
+    }
+
+    async fn mark_success(&mut self, item: QueueItem) -> Result<(), StoreError> {
+        self.marked.push((item, true));
+
+        Ok(())
+    }
+
+    async fn mark_failure(&mut self, item: QueueItem) -> Result<(), StoreError> {
+        self.marked.push((item, false));
+
+        Ok(())
+    }
+
+    async fn add(&mut self, event: String, data: String) -> Result<(), StoreError> {
+        self.added.push((event, data));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::OverlayStore;
+    use crate::entitystore::EntityStore;
+    use crate::querybuilder::QueryBuilder;
+    use crate::test::{entity, item, TestStore};
+    use async_std::task::block_on;
+
+    fn store() -> TestStore {
+        TestStore::new(vec![
+            entity("/actor", &[as2!(Person)], &[]),
+            entity("/old", &[as2!(Note)], &[(as2!(attributedTo), &["/actor"])]),
+            entity(
+                "/changed",
+                &[as2!(Note)],
+                &[(as2!(attributedTo), &["/actor"])],
+            ),
+        ])
+        .with_collection("/outbox", &["/1", "/2", "/3"])
+    }
+
+    #[test]
+    fn queries_buffered_items() {
+        let mut store = store();
+        let mut overlay = OverlayStore::new(&mut store);
+
+        let mut note = item(entity(
+            "/new",
+            &[as2!(Note)],
+            &[(as2!(attributedTo), &["/actor"])],
+        ));
+        let mut changed = item(entity(
+            "/changed",
+            &[as2!(Note)],
+            &[(as2!(attributedTo), &["/other"])],
+        ));
+        block_on(overlay.put("/new".to_owned(), &mut note)).unwrap();
+        block_on(overlay.put("/changed".to_owned(), &mut changed)).unwrap();
+
+        // Joins buffered notes with their stored authors.
+        let mut query = QueryBuilder::new();
+        let (note, actor) = (query.placeholder(), query.placeholder());
+        query
+            .attributed_to(note, actor)
+            .of_type(actor, as2!(Person));
+
+        let mut rows = block_on(overlay.query(query.build())).unwrap();
+        rows.sort();
+        assert_eq!(
+            rows,
+            vec![
+                vec!["/new".to_owned(), "/actor".to_owned()],
+                vec!["/old".to_owned(), "/actor".to_owned()],
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_buffered_collection_changes() {
+        let mut store = store();
+        let mut overlay = OverlayStore::new(&mut store);
+
+        block_on(overlay.insert_collection("/outbox".to_owned(), "/4".to_owned())).unwrap();
+        block_on(overlay.remove_collection("/outbox".to_owned(), "/2".to_owned())).unwrap();
+
+        let first = block_on(overlay.read_collection("/outbox".to_owned(), Some(2), None)).unwrap();
+        assert_eq!(first.items, vec!["/4", "/3"]);
+        assert_eq!(first.count, Some(3));
+
+        let second =
+            block_on(overlay.read_collection("/outbox".to_owned(), Some(2), first.after)).unwrap();
+        assert_eq!(second.items, vec!["/1"]);
+        assert_eq!(second.after, None);
+    }
+}
@@ -0,0 +1,312 @@
+//! Runs message handlers in order, grouped into named stages.
+
+use crate::entity::StoreItem;
+use crate::error::{ErrorKind, TapError};
+use crate::handler::{handler_applies, ActivityHandler, MessageHandler};
+use crate::overlay::{BufferedQueue, ChangeReport, OverlayStore};
+use crate::user::Context;
+
+/// A single handler within a `Stage`.
+pub enum Handler {
+    /// Handles any message, and may change its inbox or ID.
+    Message(Box<dyn MessageHandler>),
+
+    /// Handles only activities of the types it applies to.
+    Activity(Box<dyn ActivityHandler>),
+}
+
+impl Handler {
+    /// Wraps a `MessageHandler`.
+    pub fn message<T: MessageHandler + 'static>(handler: T) -> Handler {
+        Handler::Message(Box::new(handler))
+    }
+
+    /// Wraps an `ActivityHandler`.
+    pub fn activity<T: ActivityHandler + 'static>(handler: T) -> Handler {
+        Handler::Activity(Box::new(handler))
+    }
+}
+
+/// A named group of handlers within a `Pipeline`.
+pub struct Stage {
+    name: String,
+    handlers: Vec<Handler>,
+}
+
+impl Stage {
+    /// The name of this stage.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The handlers in this stage, in the order they are run.
+    pub fn handlers(&self) -> &[Handler] {
+        &self.handlers
+    }
+
+    /// Appends a handler to the end of this stage.
+    pub fn push(&mut self, handler: Handler) {
+        self.handlers.push(handler);
+    }
+
+    /// Inserts a handler at the start of this stage.
+    pub fn prepend(&mut self, handler: Handler) {
+        self.handlers.insert(0, handler);
+    }
+}
+
+/// An ordered list of stages of message handlers. Every handler is run in
+///  sequence, and sees any changes previous handlers made to the inbox or ID
+///  of the message. The first error stops the pipeline.
+///
+/// The activity is loaded once, and passed to every `ActivityHandler` whose
+///  types it matches. It is only loaded again after a `MessageHandler` ran, as
+///  those may change the message. Activity handlers should not change the
+///  activity itself.
+///
+/// A handler returning an error of kind `ErrorKind::AlreadyHandled` stops the
+///  pipeline too, but the message is then treated as handled successfully.
+///
+/// A `Pipeline` is itself a `MessageHandler`, so pipelines can be nested.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Stage>,
+}
+
+impl Pipeline {
+    /// Creates an empty pipeline.
+    pub fn new() -> Pipeline {
+        Pipeline { stages: Vec::new() }
+    }
+
+    /// Adds a stage at the end of the pipeline. If a stage by this name
+    ///  already exists, the handlers are appended to it instead.
+    pub fn with_stage(mut self, name: &str, handlers: Vec<Handler>) -> Pipeline {
+        match self.stage_mut(name) {
+            Some(stage) => stage.handlers.extend(handlers),
+            None => self.stages.push(Stage {
+                name: name.to_owned(),
+                handlers,
+            }),
+        }
+
+        self
+    }
+
+    /// Inserts a new, empty stage right before another stage. Returns false if
+    ///  `before` doesn't exist, or a stage named `name` already exists.
+    pub fn insert_stage_before(&mut self, before: &str, name: &str) -> bool {
+        if self.stage(name).is_some() {
+            return false;
+        }
+
+        match self.stages.iter().position(|f| f.name == before) {
+            Some(index) => {
+                self.stages.insert(
+                    index,
+                    Stage {
+                        name: name.to_owned(),
+                        handlers: Vec::new(),
+                    },
+                );
+
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Removes a stage from the pipeline, returning it if it existed.
+    pub fn remove_stage(&mut self, name: &str) -> Option<Stage> {
+        let index = self.stages.iter().position(|f| f.name == name)?;
+
+        Some(self.stages.remove(index))
+    }
+
+    /// Returns the stage with the specified name, if any.
+    pub fn stage(&self, name: &str) -> Option<&Stage> {
+        self.stages.iter().find(|f| f.name == name)
+    }
+
+    /// Returns the stage with the specified name, if any, to add handlers to.
+    pub fn stage_mut(&mut self, name: &str) -> Option<&mut Stage> {
+        self.stages.iter_mut().find(|f| f.name == name)
+    }
+
+    /// All stages of this pipeline, in the order they are run.
+    pub fn stages(&self) -> &[Stage] {
+        &self.stages
+    }
+
+    /// Runs all the handlers in this pipeline on a single message, atomically.
+    ///
+    /// If any handler fails, none of the changes to the entity store or the
+    ///  queue are kept. This uses the transactions of the entity store if it
+    ///  supports them, or buffers all changes in an `OverlayStore` otherwise.
+    ///
+    /// If a handler reports the message was already handled, the changes are
+    ///  discarded too, and `Ok` is returned.
+    ///
+    /// Without transactions, this is only atomic up to the point the handlers
+    ///  succeeded: the buffered changes are then written one by one, and if
+    ///  the store fails halfway, the changes written before are kept. The
+    ///  queue is committed after the entity store, so if that fails, the
+    ///  changes are kept, but nothing is delivered.
+    pub async fn run(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &mut String,
+        id: &mut String,
+    ) -> Result<(), TapError> {
+        match self.run_atomic(context, inbox, id).await {
+            Err(e) if e.kind() == ErrorKind::AlreadyHandled => Ok(()),
+            result => result,
+        }
+    }
+
+    async fn run_atomic(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &mut String,
+        id: &mut String,
+    ) -> Result<(), TapError> {
+        let mut queue = BufferedQueue::new(context.queue_store);
+
+        if context.entity_store.begin_transaction().await? {
+            let result = {
+                let mut context = Context {
+                    user: context.user.clone(),
+                    server_base: context.server_base.clone(),
+                    name: context.name.clone(),
+                    description: context.description.clone(),
+                    entity_store: context.entity_store,
+                    queue_store: &mut queue,
+                    instance_id: context.instance_id,
+                    id_strategy: context.id_strategy.clone(),
+                };
+
+                self.run_handlers(&mut context, inbox, id).await
+            };
+
+            match result {
+                Ok(()) => context.entity_store.commit_transaction().await?,
+                Err(e) => {
+                    context.entity_store.rollback_transaction().await?;
+                    return Err(e);
+                }
+            }
+        } else {
+            let mut store = OverlayStore::new(context.entity_store);
+            {
+                let mut context = Context {
+                    user: context.user.clone(),
+                    server_base: context.server_base.clone(),
+                    name: context.name.clone(),
+                    description: context.description.clone(),
+                    entity_store: &mut store,
+                    queue_store: &mut queue,
+                    instance_id: context.instance_id,
+                    id_strategy: context.id_strategy.clone(),
+                };
+
+                self.run_handlers(&mut context, inbox, id).await?;
+            }
+
+            store.commit().await?;
+        }
+
+        queue.commit().await
+    }
+
+    /// Runs all the handlers in this pipeline on a single message, without
+    ///  changing the entity store or queue. Returns everything the pipeline
+    ///  would have changed instead.
+    ///
+    /// Errors of the handlers are returned as usual. If the message was
+    ///  already handled, an empty report is returned.
+    pub async fn dry_run(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &mut String,
+        id: &mut String,
+    ) -> Result<ChangeReport, TapError> {
+        let mut store = OverlayStore::new(context.entity_store);
+        let mut queue = BufferedQueue::new(context.queue_store);
+
+        {
+            let mut context = Context {
+                user: context.user.clone(),
+                server_base: context.server_base.clone(),
+                name: context.name.clone(),
+                description: context.description.clone(),
+                entity_store: &mut store,
+                queue_store: &mut queue,
+                instance_id: context.instance_id,
+                id_strategy: context.id_strategy.clone(),
+            };
+
+            match self.run_handlers(&mut context, inbox, id).await {
+                Err(e) if e.kind() == ErrorKind::AlreadyHandled => {
+                    return Ok(ChangeReport::default())
+                }
+                result => result?,
+            }
+        }
+
+        Ok(ChangeReport {
+            changes: store.into_changes(),
+            queued: queue.into_added(),
+        })
+    }
+
+    async fn run_handlers(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &mut String,
+        id: &mut String,
+    ) -> Result<(), TapError> {
+        let mut activity: Option<Option<StoreItem>> = None;
+
+        for stage in &self.stages {
+            for handler in &stage.handlers {
+                match handler {
+                    Handler::Message(handler) => {
+                        handler.handle(context, inbox, id).await?;
+                        activity = None;
+                    }
+
+                    Handler::Activity(handler) => {
+                        if activity.is_none() {
+                            activity = Some(context.entity_store.get(id.to_owned(), false).await?);
+                        }
+
+                        if let Some(Some(activity)) = &activity {
+                            if handler_applies(handler.applies_to(), activity) {
+                                handler.handle_activity(context, inbox, activity).await?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A pipeline can be a handler in another pipeline. It then runs its handlers
+///  as part of the transaction of the outer pipeline, instead of starting one
+///  of its own, so its changes are kept or discarded along with the others.
+///  A message that was already handled stops the outer pipeline as well.
+#[async_trait::async_trait]
+impl MessageHandler for Pipeline {
+    async fn handle(
+        &self,
+        context: &mut Context<'_, '_>,
+        inbox: &mut String,
+        id: &mut String,
+    ) -> Result<(), TapError> {
+        self.run_handlers(context, inbox, id).await
+    }
+}
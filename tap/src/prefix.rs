@@ -0,0 +1,103 @@
+use std::collections::BTreeMap;
+
+/// The prefixes known to `PrefixMap::default()`.
+pub const DEFAULT_PREFIXES: &'static [(&'static str, &'static str)] = &[
+    ("as", as2!()),
+    ("kroeg", kroeg!()),
+    ("ldp", ldp!()),
+    ("litepub", litepub!()),
+    ("ostatus", ostatus!()),
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("schema", "http://schema.org#"), // XXX fix in Mastodon. maybe add to supplement?
+    ("sec", sec!()),
+    ("toot", toot!()),
+    ("vcard", vcard!()),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+];
+
+/// A map of namespace prefixes, used to translate between short `as:Note`
+///  style names and full IRIs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefixMap {
+    prefixes: BTreeMap<String, String>,
+}
+
+impl PrefixMap {
+    /// Creates an empty prefix map. Use `PrefixMap::default()` to get one
+    ///  containing the `DEFAULT_PREFIXES`.
+    pub fn new() -> PrefixMap {
+        PrefixMap {
+            prefixes: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a prefix, returning the namespace it previously mapped to.
+    pub fn insert(&mut self, prefix: &str, namespace: &str) -> Option<String> {
+        self.prefixes
+            .insert(prefix.to_owned(), namespace.to_owned())
+    }
+
+    /// Removes a prefix, returning the namespace it mapped to.
+    pub fn remove(&mut self, prefix: &str) -> Option<String> {
+        self.prefixes.remove(prefix)
+    }
+
+    /// Gets the namespace a prefix maps to.
+    pub fn get(&self, prefix: &str) -> Option<&str> {
+        self.prefixes.get(prefix).map(|f| f as &str)
+    }
+
+    /// Iterates over all prefixes and their namespaces, ordered by prefix.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.prefixes.iter().map(|(k, v)| (k as &str, v as &str))
+    }
+
+    /// Expands a prefixed name like `as:Note` to a full IRI. Returns `None`
+    ///  if the name has no prefix, the prefix is unknown, or the name is an
+    ///  IRI itself, e.g. `https://example.com`.
+    pub fn expand(&self, name: &str) -> Option<String> {
+        let mut split = name.splitn(2, ':');
+        let (prefix, local) = (split.next()?, split.next()?);
+
+        if local.starts_with("//") {
+            return None;
+        }
+
+        self.get(prefix)
+            .map(|namespace| format!("{}{}", namespace, local))
+    }
+
+    /// Compacts a full IRI to a prefixed name like `as:Note`, using the
+    ///  longest namespace that matches. Returns `None` if no namespace
+    ///  matches, or if the remainder wouldn't survive expanding again.
+    pub fn compact(&self, iri: &str) -> Option<String> {
+        self.prefixes
+            .iter()
+            .filter(|(_, namespace)| iri.starts_with(namespace as &str))
+            .max_by_key(|(_, namespace)| namespace.len())
+            .and_then(|(prefix, namespace)| {
+                let local = &iri[namespace.len()..];
+                let valid = !local.is_empty()
+                    && !local.starts_with("//")
+                    && !local.ends_with('.')
+                    && !local.chars().any(|f| f.is_whitespace() || f == '#');
+
+                if valid {
+                    Some(format!("{}:{}", prefix, local))
+                } else {
+                    None
+                }
+            })
+    }
+}
+
+impl Default for PrefixMap {
+    fn default() -> PrefixMap {
+        let mut map = PrefixMap::new();
+        for (prefix, namespace) in DEFAULT_PREFIXES {
+            map.insert(prefix, namespace);
+        }
+
+        map
+    }
+}
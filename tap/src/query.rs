@@ -1,8 +1,16 @@
-use std::num::ParseIntError;
-use std::str::FromStr;
+use chrono::{DateTime, Utc};
+use std::cmp::Ordering;
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::{CharIndices, FromStr};
+
+use crate::error::{ErrorKind, TapError};
+use crate::prefix::PrefixMap;
+use crate::querybuilder::Placeholder;
 
 /// An ID value in a query.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum QueryId {
     /// A static ID value.
     Value(String),
@@ -17,92 +25,950 @@ pub enum QueryId {
     Ignore,
 }
 
-impl FromStr for QueryId {
-    type Err = ParseIntError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(if s == "_" {
-            QueryId::Ignore
-        } else if s.starts_with("<") {
-            QueryId::Value(s[1..s.len() - 1].to_owned())
-        } else if s.contains(":") && !s.contains("://") {
-            let split: Vec<_> = s.split(':').collect();
-            if split.len() != 2 {
-                QueryId::Value(s.to_owned())
-            } else {
-                match split[0] {
-                    "as" => QueryId::Value(format!(
-                        "https://www.w3.org/ns/activitystreams#{}",
-                        split[1]
-                    )),
-                    "kroeg" => {
-                        QueryId::Value(format!("https://puckipedia.com/kroeg/ns#{}", split[1]))
-                    }
-                    "ldp" => QueryId::Value(format!("http://www.w3.org/ns/ldp#{}", split[1])),
-                    "ostatus" => QueryId::Value(format!("http://ostatus.org#{}", split[1])),
-                    "rdf" => QueryId::Value(format!(
-                        "http://www.w3.org/1999/02/22-rdf-syntax-ns#{}",
-                        split[1]
-                    )),
-                    "schema" => QueryId::Value(format!("http://schema.org#{}", split[1])), // XXX fix in Mastodon. maybe add to supplement?
-                    "toot" => QueryId::Value(format!("http://joinmastodon.org/ns#{}", split[1])),
-                    "xsd" => {
-                        QueryId::Value(format!("http://www.w3.org/2001/XMLSchema#{}", split[1]))
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryObject {
+    Id(QueryId),
+    Object { value: String, type_id: QueryId },
+    LanguageString { value: String, language: String },
+}
+
+/// A single quad in a query: a subject, predicate, and object.
+///
+/// Queries are written one quad per line, each consisting of three terms
+///  separated by whitespace. A quad may also be ended with a `.`, and `#`
+///  starts a comment that runs until the end of the line. Terms are:
+///  - `_`, which matches anything
+///  - `?0`, a placeholder
+///  - `<https://example.com/>`, an IRI. `>`, `\`, whitespace and control
+///    characters have to be escaped, using the same escapes as literals
+///  - `as:Note`, a prefixed name, expanded using a `PrefixMap`
+///  - `https://example.com/`, a bare IRI, which ends at whitespace
+///  - `[as:Note as:Article]`, which matches any of the IRIs in it
+///  - `"text"`, `"text"@en`, or `"5"^^xsd:integer`, literals, only allowed
+///    as object. Literals support the `\"`, `\\`, `\n`, `\r`, `\t` and
+///    `\u{...}` escapes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuadQuery(pub QueryId, pub QueryId, pub QueryObject);
+
+impl QuadQuery {
+    /// Parses a query document containing any amount of quads, using the
+    ///  default prefixes.
+    pub fn parse_all(s: &str) -> Result<Vec<QuadQuery>, QueryParseError> {
+        QuadQuery::parse_all_with(s, &PrefixMap::default())
+    }
+
+    /// Parses a query document containing any amount of quads, using the
+    ///  prefixes in `prefixes`.
+    pub fn parse_all_with(
+        s: &str,
+        prefixes: &PrefixMap,
+    ) -> Result<Vec<QuadQuery>, QueryParseError> {
+        Parser::new(s, prefixes).parse_all()
+    }
+}
+
+/// How the value of a placeholder is compared in a `Filter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Comparison {
+    /// Checks if an ordering between a value and the value it's compared to
+    ///  satisfies this comparison.
+    pub fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Comparison::Equal => ordering == Ordering::Equal,
+            Comparison::NotEqual => ordering != Ordering::Equal,
+            Comparison::Less => ordering == Ordering::Less,
+            Comparison::LessOrEqual => ordering != Ordering::Greater,
+            Comparison::Greater => ordering == Ordering::Greater,
+            Comparison::GreaterOrEqual => ordering != Ordering::Less,
+        }
+    }
+}
+
+/// A condition on the value bound to a placeholder. Rows in which the
+///  placeholder is unbound, or bound to a value of the wrong kind, never
+///  match a filter.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Compares the value as a number.
+    Number(Placeholder, Comparison, f64),
+
+    /// Compares the value as an RFC 3339 date, e.g. `as:published`.
+    Date(Placeholder, Comparison, DateTime<Utc>),
+
+    /// Requires the value to start with a string, e.g. the base of a server.
+    StartsWith(Placeholder, String),
+
+    /// Requires the value to be a string in a language, e.g. `en` also
+    ///  matches `en-GB`. `*` matches any string with a language.
+    Language(Placeholder, String),
+}
+
+/// A sort key of a `Query`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OrderBy {
+    pub placeholder: Placeholder,
+    pub descending: bool,
+}
+
+/// A query that's more expressive than a list of `QuadQuery`, for use with
+///  `EntityStore::select`.
+///
+/// Every row that matches all `patterns` is extended with the matches of each
+///  of the `optional` groups, if any. Rows for which any of the `not` groups
+///  match are removed, as are rows that don't match all `filters`. The rows
+///  left are then sorted, and `offset` and `limit` are applied.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Query {
+    pub patterns: Vec<QuadQuery>,
+    pub optional: Vec<Vec<QuadQuery>>,
+    pub not: Vec<Vec<QuadQuery>>,
+    pub filters: Vec<Filter>,
+    pub order: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+impl Query {
+    pub fn new(patterns: Vec<QuadQuery>) -> Query {
+        Query {
+            patterns,
+            ..Default::default()
+        }
+    }
+
+    /// Adds a group of patterns that may or may not match.
+    pub fn with_optional(mut self, patterns: Vec<QuadQuery>) -> Query {
+        self.optional.push(patterns);
+        self
+    }
+
+    /// Adds a group of patterns that may not match.
+    pub fn without(mut self, patterns: Vec<QuadQuery>) -> Query {
+        self.not.push(patterns);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: Filter) -> Query {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Sorts the results by the value of a placeholder. Can be called
+    ///  multiple times to sort by multiple placeholders.
+    pub fn order_by(mut self, placeholder: Placeholder, descending: bool) -> Query {
+        self.order.push(OrderBy {
+            placeholder,
+            descending,
+        });
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Query {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn with_offset(mut self, offset: usize) -> Query {
+        self.offset = offset;
+        self
+    }
+
+    /// Checks if this query only consists of patterns, limit and offset,
+    ///  which every store supports through `EntityStore::query`.
+    pub fn is_basic(&self) -> bool {
+        self.optional.is_empty()
+            && self.not.is_empty()
+            && self.filters.is_empty()
+            && self.order.is_empty()
+    }
+
+    /// The amount of values in each row of the results, which is one more
+    ///  than the highest placeholder used in the query.
+    pub fn width(&self) -> usize {
+        fn id_width(id: &QueryId) -> usize {
+            match id {
+                QueryId::Placeholder(i) => *i as usize + 1,
+                _ => 0,
+            }
+        }
+
+        self.patterns
+            .iter()
+            .chain(self.optional.iter().flatten())
+            .map(|QuadQuery(subject, predicate, object)| {
+                let object = match object {
+                    QueryObject::Id(id) => id_width(id),
+                    QueryObject::Object { type_id, .. } => id_width(type_id),
+                    QueryObject::LanguageString { .. } => 0,
+                };
+
+                id_width(subject).max(id_width(predicate)).max(object)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+fn write_literal(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in value.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            ch if ch.is_control() => write!(f, "\\u{{{:x}}}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+
+    write!(f, "\"")
+}
+
+fn write_iri(f: &mut fmt::Formatter, value: &str) -> fmt::Result {
+    write!(f, "<")?;
+    for ch in value.chars() {
+        match ch {
+            '\\' => write!(f, "\\\\")?,
+            '>' => write!(f, "\\u{{3e}}")?,
+            ch if ch.is_whitespace() || ch.is_control() => write!(f, "\\u{{{:x}}}", ch as u32)?,
+            ch => write!(f, "{}", ch)?,
+        }
+    }
+
+    write!(f, ">")
+}
+
+/// Writes the ID in the syntax accepted by the parser. IRIs are always
+///  written in full, between `<` and `>`.
+impl fmt::Display for QueryId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryId::Value(ref value) => write_iri(f, value),
+            QueryId::Placeholder(placeholder) => write!(f, "?{}", placeholder),
+            QueryId::Any(ref values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
                     }
-                    _ => QueryId::Value(s.to_owned()),
+
+                    write_iri(f, value)?;
                 }
+
+                write!(f, "]")
             }
-        } else if s.starts_with("?") {
-            QueryId::Placeholder(s[1..].parse()?)
-        } else {
-            QueryId::Value(s.to_owned())
-        })
+            QueryId::Ignore => write!(f, "_"),
+        }
+    }
+}
+
+impl fmt::Display for QueryObject {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryObject::Id(ref id) => write!(f, "{}", id),
+            QueryObject::Object {
+                ref value,
+                ref type_id,
+            } => {
+                write_literal(f, value)?;
+                match type_id {
+                    QueryId::Ignore => Ok(()),
+                    type_id => write!(f, "^^{}", type_id),
+                }
+            }
+            QueryObject::LanguageString {
+                ref value,
+                ref language,
+            } => {
+                write_literal(f, value)?;
+                write!(f, "@{}", language)
+            }
+        }
+    }
+}
+
+impl fmt::Display for QuadQuery {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.0, self.1, self.2)
+    }
+}
+
+/// The reason a query couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueryParseErrorKind {
+    /// A string literal was not closed before the end of the input.
+    UnterminatedString,
+
+    /// An IRI between `<` and `>` was not closed before the end of the line.
+    UnterminatedIri,
+
+    /// A list between `[` and `]` was not closed before the end of the line.
+    UnterminatedList,
+
+    /// A list contained something other than IRIs.
+    InvalidListItem,
+
+    /// An unknown escape sequence was found in a literal.
+    InvalidEscape(String),
+
+    /// A placeholder is not a number, e.g. `?a`.
+    InvalidPlaceholder(String),
+
+    /// A literal was found in a place where only IDs are allowed.
+    UnexpectedLiteral,
+
+    /// A character that can't start a term was found.
+    UnexpectedCharacter(char),
+
+    /// The quad ended before all three terms were found.
+    MissingTerm,
+
+    /// The quad has more than three terms.
+    TrailingTerm,
+
+    /// The input contained no quad, while one was expected.
+    Empty,
+}
+
+impl fmt::Display for QueryParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryParseErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            QueryParseErrorKind::UnterminatedIri => write!(f, "unterminated IRI"),
+            QueryParseErrorKind::UnterminatedList => write!(f, "unterminated list"),
+            QueryParseErrorKind::InvalidListItem => write!(f, "lists may only contain IRIs"),
+            QueryParseErrorKind::InvalidEscape(ref escape) => {
+                write!(f, "invalid escape sequence \\{}", escape)
+            }
+            QueryParseErrorKind::InvalidPlaceholder(ref placeholder) => {
+                write!(f, "invalid placeholder ?{}", placeholder)
+            }
+            QueryParseErrorKind::UnexpectedLiteral => {
+                write!(f, "a literal is only allowed as object")
+            }
+            QueryParseErrorKind::UnexpectedCharacter(ch) => {
+                write!(f, "unexpected character {:?}", ch)
+            }
+            QueryParseErrorKind::MissingTerm => write!(f, "expected three terms in quad"),
+            QueryParseErrorKind::TrailingTerm => write!(f, "more than three terms in quad"),
+            QueryParseErrorKind::Empty => write!(f, "expected a quad"),
+        }
+    }
+}
+
+/// An error while parsing a query, including the position it occured at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryParseError {
+    pub kind: QueryParseErrorKind,
+
+    /// The line the error occured on, starting at 1.
+    pub line: usize,
+
+    /// The column the error occured on, in characters, starting at 1.
+    pub column: usize,
+
+    /// The offset in the input the error occured at, in bytes.
+    pub offset: usize,
+}
+
+impl fmt::Display for QueryParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.kind, self.line, self.column)
+    }
+}
+
+impl Error for QueryParseError {}
+
+impl From<QueryParseError> for TapError {
+    fn from(error: QueryParseError) -> TapError {
+        TapError::new(ErrorKind::InvalidInput, error)
+    }
+}
+
+/// A position in the input of the parser.
+#[derive(Debug, Clone, Copy)]
+struct Position {
+    line: usize,
+    column: usize,
+    offset: usize,
+}
+
+impl Position {
+    fn error(self, kind: QueryParseErrorKind) -> QueryParseError {
+        QueryParseError {
+            kind,
+            line: self.line,
+            column: self.column,
+            offset: self.offset,
+        }
     }
 }
 
 #[derive(Debug)]
-pub enum QueryObject {
+enum Token {
+    /// A term that is an ID.
     Id(QueryId),
-    Object { value: String, type_id: QueryId },
-    LanguageString { value: String, language: String },
+
+    /// A literal, optionally with a type or language.
+    Literal(QueryObject),
+
+    /// The end of a quad, either `.` or a newline.
+    End,
 }
 
-impl FromStr for QueryObject {
-    type Err = <QueryId as FromStr>::Err;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if s.starts_with("\"") {
-            let (one, two) = s[1..].split_at(s[1..].find('"').unwrap());
-            if two.starts_with("\"^^") {
-                Ok(QueryObject::Object {
-                    value: one.to_owned(),
-                    type_id: two[3..].parse()?,
-                })
-            } else if two.starts_with("\"@") {
+struct Parser<'a> {
+    input: &'a str,
+    prefixes: &'a PrefixMap,
+    chars: Peekable<CharIndices<'a>>,
+    line: usize,
+    column: usize,
+    pending_end: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str, prefixes: &'a PrefixMap) -> Parser<'a> {
+        Parser {
+            input,
+            prefixes,
+            chars: input.char_indices().peekable(),
+            line: 1,
+            column: 1,
+            pending_end: false,
+        }
+    }
+
+    fn position(&mut self) -> Position {
+        let offset = match self.chars.peek() {
+            Some((offset, _)) => *offset,
+            None => self.input.len(),
+        };
+
+        Position {
+            line: self.line,
+            column: self.column,
+            offset,
+        }
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.chars.peek().map(|(_, ch)| *ch)
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let (_, ch) = self.chars.next()?;
+        if ch == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Some(ch)
+    }
+
+    /// Skips whitespace and comments, but not newlines.
+    fn skip_whitespace(&mut self) {
+        while let Some(ch) = self.peek() {
+            if ch == '#' {
+                while self.peek().map(|f| f != '\n').unwrap_or(false) {
+                    self.next();
+                }
+            } else if ch.is_whitespace() && ch != '\n' {
+                self.next();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Reads a bare word, up to the next whitespace or `]`.
+    fn word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(ch) = self.peek() {
+            if ch.is_whitespace() || ch == ']' {
+                break;
+            }
+
+            word.push(ch);
+            self.next();
+        }
+
+        word
+    }
+
+    /// Reads a language tag, which consists of letters, digits and `-`.
+    fn language(&mut self) -> String {
+        let mut language = String::new();
+        while let Some(ch) = self.peek() {
+            if !ch.is_ascii_alphanumeric() && ch != '-' {
+                break;
+            }
+
+            language.push(ch);
+            self.next();
+        }
+
+        language
+    }
+
+    fn iri(&mut self, start: Position) -> Result<String, QueryParseError> {
+        self.next();
+
+        let mut iri = String::new();
+        loop {
+            let position = self.position();
+            match self.next() {
+                Some('>') => return Ok(iri),
+                Some('\\') => iri.push(self.escape(position)?),
+                Some('\n') | None => return Err(start.error(QueryParseErrorKind::UnterminatedIri)),
+                Some(ch) if ch.is_whitespace() => {
+                    return Err(start.error(QueryParseErrorKind::UnterminatedIri))
+                }
+                Some(ch) => iri.push(ch),
+            }
+        }
+    }
+
+    fn list(&mut self, start: Position) -> Result<QueryId, QueryParseError> {
+        self.next();
+
+        let mut items = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(']') => {
+                    self.next();
+                    return Ok(QueryId::Any(items));
+                }
+
+                Some('\n') | None => return Err(start.error(QueryParseErrorKind::UnterminatedList)),
+
+                _ => {}
+            }
+
+            let position = self.position();
+            match self.token()? {
+                Some(Token::Id(QueryId::Value(item))) if !self.pending_end => items.push(item),
+                _ => return Err(position.error(QueryParseErrorKind::InvalidListItem)),
+            }
+        }
+    }
+
+    fn escape(&mut self, start: Position) -> Result<char, QueryParseError> {
+        Ok(match self.next() {
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('u') => {
+                if self.next() != Some('{') {
+                    return Err(start.error(QueryParseErrorKind::InvalidEscape("u".to_owned())));
+                }
+
+                let mut hex = String::new();
+                loop {
+                    match self.next() {
+                        Some('}') => break,
+                        Some(ch) if ch.is_ascii_hexdigit() && hex.len() < 6 => hex.push(ch),
+                        _ => {
+                            return Err(start
+                                .error(QueryParseErrorKind::InvalidEscape(format!("u{{{}", hex))))
+                        }
+                    }
+                }
+
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| {
+                        start.error(QueryParseErrorKind::InvalidEscape(format!("u{{{}}}", hex)))
+                    })?
+            }
+
+            Some(ch) => return Err(start.error(QueryParseErrorKind::InvalidEscape(ch.to_string()))),
+            None => return Err(start.error(QueryParseErrorKind::UnterminatedString)),
+        })
+    }
+
+    fn literal(&mut self, start: Position) -> Result<QueryObject, QueryParseError> {
+        self.next();
+
+        let mut value = String::new();
+        loop {
+            let position = self.position();
+            match self.next() {
+                Some('"') => break,
+                Some('\\') => value.push(self.escape(position)?),
+                Some(ch) => value.push(ch),
+                None => return Err(start.error(QueryParseErrorKind::UnterminatedString)),
+            }
+        }
+
+        match self.peek() {
+            Some('@') => {
+                self.next();
                 Ok(QueryObject::LanguageString {
-                    value: one.to_owned(),
-                    language: two[2..].to_owned(),
+                    value,
+                    language: self.language(),
                 })
-            } else {
-                Ok(QueryObject::Object {
-                    value: one.to_owned(),
-                    type_id: two[1..].parse()?,
+            }
+
+            Some('^') => {
+                self.next();
+                if self.peek() == Some('^') {
+                    self.next();
+                }
+
+                let position = self.position();
+                match self.token()? {
+                    Some(Token::Id(type_id)) => Ok(QueryObject::Object { value, type_id }),
+                    Some(Token::Literal(_)) => {
+                        Err(position.error(QueryParseErrorKind::UnexpectedLiteral))
+                    }
+                    _ => Err(position.error(QueryParseErrorKind::MissingTerm)),
+                }
+            }
+
+            Some(ch) if !ch.is_whitespace() && ch != '.' && ch != '#' => match self.token()? {
+                Some(Token::Id(type_id)) => Ok(QueryObject::Object { value, type_id }),
+                _ => Err(start.error(QueryParseErrorKind::UnexpectedLiteral)),
+            },
+
+            _ => Ok(QueryObject::Object {
+                value,
+                type_id: QueryId::Ignore,
+            }),
+        }
+    }
+
+    /// Reads the next token, or `None` at the end of the input.
+    fn token(&mut self) -> Result<Option<Token>, QueryParseError> {
+        if self.pending_end {
+            self.pending_end = false;
+            return Ok(Some(Token::End));
+        }
+
+        self.skip_whitespace();
+
+        let start = self.position();
+        let ch = match self.peek() {
+            Some(ch) => ch,
+            None => return Ok(None),
+        };
+
+        Ok(Some(match ch {
+            '\n' | '.' => {
+                self.next();
+                Token::End
+            }
+
+            '"' => Token::Literal(self.literal(start)?),
+            '<' => Token::Id(QueryId::Value(self.iri(start)?)),
+            '[' => Token::Id(self.list(start)?),
+
+            ch if ch.is_control() => {
+                return Err(start.error(QueryParseErrorKind::UnexpectedCharacter(ch)))
+            }
+
+            _ => {
+                let mut word = self.word();
+
+                // A `.` at the end of a bare word ends the quad.
+                if word.len() > 1 && word.ends_with('.') {
+                    word.pop();
+                    self.pending_end = true;
+                }
+
+                Token::Id(if word == "_" {
+                    QueryId::Ignore
+                } else if word.starts_with('?') {
+                    QueryId::Placeholder(word[1..].parse().map_err(|_| {
+                        start.error(QueryParseErrorKind::InvalidPlaceholder(
+                            word[1..].to_owned(),
+                        ))
+                    })?)
+                } else {
+                    QueryId::Value(self.prefixes.expand(&word).unwrap_or(word))
                 })
             }
-        } else {
-            Ok(QueryObject::Id(s.parse()?))
+        }))
+    }
+
+    /// Reads the next term, which has to be an ID.
+    fn id(&mut self) -> Result<QueryId, QueryParseError> {
+        self.skip_whitespace();
+        let position = self.position();
+
+        match self.token()? {
+            Some(Token::Id(id)) => Ok(id),
+            Some(Token::Literal(_)) => Err(position.error(QueryParseErrorKind::UnexpectedLiteral)),
+            _ => Err(position.error(QueryParseErrorKind::MissingTerm)),
+        }
+    }
+
+    /// Reads the next term, which may be an ID or a literal.
+    fn object(&mut self) -> Result<QueryObject, QueryParseError> {
+        self.skip_whitespace();
+        let position = self.position();
+
+        match self.token()? {
+            Some(Token::Id(id)) => Ok(QueryObject::Id(id)),
+            Some(Token::Literal(literal)) => Ok(literal),
+            _ => Err(position.error(QueryParseErrorKind::MissingTerm)),
+        }
+    }
+
+    /// Reads the next quad, skipping empty lines. Returns `None` at the end
+    ///  of the input.
+    fn quad(&mut self) -> Result<Option<QuadQuery>, QueryParseError> {
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return Ok(None),
+                Some('\n') | Some('.') => {
+                    self.next();
+                }
+                Some(_) => break,
+            }
+        }
+
+        let quad = QuadQuery(self.id()?, self.id()?, self.object()?);
+
+        self.skip_whitespace();
+        let position = self.position();
+        match self.token()? {
+            None | Some(Token::End) => Ok(Some(quad)),
+            Some(_) => Err(position.error(QueryParseErrorKind::TrailingTerm)),
+        }
+    }
+
+    fn parse_all(mut self) -> Result<Vec<QuadQuery>, QueryParseError> {
+        let mut result = Vec::new();
+        while let Some(quad) = self.quad()? {
+            result.push(quad);
+        }
+
+        Ok(result)
+    }
+
+    /// Parses a single value using `parse`, and ensures nothing follows it.
+    fn parse_single<T>(
+        mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, QueryParseError>,
+    ) -> Result<T, QueryParseError> {
+        self.skip_whitespace();
+        if self.peek().is_none() {
+            return Err(self.position().error(QueryParseErrorKind::Empty));
+        }
+
+        let result = parse(&mut self)?;
+
+        loop {
+            self.skip_whitespace();
+            let position = self.position();
+            match self.token()? {
+                None => return Ok(result),
+                Some(Token::End) => continue,
+                Some(_) => return Err(position.error(QueryParseErrorKind::TrailingTerm)),
+            }
         }
     }
 }
 
-#[derive(Debug)]
-pub struct QuadQuery(pub QueryId, pub QueryId, pub QueryObject);
+impl FromStr for QueryId {
+    type Err = QueryParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s, &PrefixMap::default()).parse_single(|f| f.id())
+    }
+}
+
+impl FromStr for QueryObject {
+    type Err = QueryParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Parser::new(s, &PrefixMap::default()).parse_single(|f| f.object())
+    }
+}
 
 impl FromStr for QuadQuery {
-    type Err = ParseIntError;
+    type Err = QueryParseError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let (first, s) = s.split_at(s.find(' ').unwrap());
-        let (second, third) = s[1..].split_at(s[1..].find(' ').unwrap());
-        let third = &third[1..];
+        Parser::new(s, &PrefixMap::default()).parse_single(|f| match f.quad()? {
+            Some(quad) => Ok(quad),
+            None => Err(f.position().error(QueryParseErrorKind::Empty)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{QuadQuery, QueryId, QueryObject, QueryParseErrorKind};
+    use crate::prefix::PrefixMap;
+    use crate::querybuilder::QueryBuilder;
+
+    fn value(s: &str) -> QueryId {
+        QueryId::Value(s.to_owned())
+    }
+
+    #[test]
+    fn parses_quad() {
+        let quad: QuadQuery = "?0 as:attributedTo <https://example.com/~puck>"
+            .parse()
+            .unwrap();
+
+        assert_eq!(
+            quad,
+            QuadQuery(
+                QueryId::Placeholder(0),
+                value(as2!(attributedTo)),
+                QueryObject::Id(value("https://example.com/~puck"))
+            )
+        );
+    }
+
+    #[test]
+    fn parses_literals() {
+        let quads = QuadQuery::parse_all(
+            r#"
+            # comments and empty lines are skipped
+            _ as:content "a \"quoted\" text\twith spaces" .
+            _ as:name "naam"@nl.
+            _ as:totalItems "5"^^xsd:integer
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            quads.into_iter().map(|f| f.2).collect::<Vec<_>>(),
+            vec![
+                QueryObject::Object {
+                    value: "a \"quoted\" text\twith spaces".to_owned(),
+                    type_id: QueryId::Ignore,
+                },
+                QueryObject::LanguageString {
+                    value: "naam".to_owned(),
+                    language: "nl".to_owned(),
+                },
+                QueryObject::Object {
+                    value: "5".to_owned(),
+                    type_id: value("http://www.w3.org/2001/XMLSchema#integer"),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_bare_iris() {
+        let quads =
+            QuadQuery::parse_all("https://example.com/a:b rdf:type urn:x:y. _ _ _").unwrap();
+
+        assert_eq!(
+            quads,
+            vec![
+                QuadQuery(
+                    value("https://example.com/a:b"),
+                    value("http://www.w3.org/1999/02/22-rdf-syntax-ns#type"),
+                    QueryObject::Id(value("urn:x:y"))
+                ),
+                QuadQuery(
+                    QueryId::Ignore,
+                    QueryId::Ignore,
+                    QueryObject::Id(QueryId::Ignore)
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_position() {
+        let error = QuadQuery::parse_all("_ _ _\n_ as:content \"unterminated").unwrap_err();
+
+        assert_eq!(error.kind, QueryParseErrorKind::UnterminatedString);
+        assert_eq!((error.line, error.column, error.offset), (2, 14, 19));
+    }
+
+    #[test]
+    fn refuses_invalid_quads() {
+        let errors: Vec<_> = [
+            "_ _",
+            "_ _ _ _",
+            "?a _ _",
+            "_ \"literal\" _",
+            "_ _ <unclosed",
+        ]
+        .iter()
+        .map(|f| f.parse::<QuadQuery>().unwrap_err().kind)
+        .collect();
+
+        assert_eq!(
+            errors,
+            vec![
+                QueryParseErrorKind::MissingTerm,
+                QueryParseErrorKind::TrailingTerm,
+                QueryParseErrorKind::InvalidPlaceholder("a".to_owned()),
+                QueryParseErrorKind::UnexpectedLiteral,
+                QueryParseErrorKind::UnterminatedIri,
+            ]
+        );
+    }
+
+    #[test]
+    fn uses_prefix_map() {
+        let mut prefixes = PrefixMap::default();
+        prefixes.insert("ex", "https://example.com/ns#");
+
+        let quads = QuadQuery::parse_all_with("?0 ex:mood sec:publicKey", &prefixes).unwrap();
+        assert_eq!(
+            quads,
+            vec![QuadQuery(
+                QueryId::Placeholder(0),
+                value("https://example.com/ns#mood"),
+                QueryObject::Id(value(sec!(publicKey)))
+            )]
+        );
+
+        assert_eq!(
+            prefixes.compact("https://example.com/ns#mood"),
+            Some("ex:mood".to_owned())
+        );
+        assert_eq!(prefixes.compact("https://example.com/other"), None);
+    }
+
+    #[test]
+    fn round_trips_through_display() {
+        let mut query =
+            QueryBuilder::of_type_attributed_to(as2!(Note), "https://example.com/~puck");
+        let reply = query.placeholder();
+        query
+            .in_reply_to(reply, QueryId::Placeholder(0))
+            .quad(
+                reply,
+                QueryId::any(vec![as2!(content), as2!(summary)]),
+                QueryObject::language("a \"quote\"\n\u{7}", "en"),
+            )
+            .quad(
+                QueryId::Ignore,
+                as2!(totalItems),
+                QueryObject::typed("5", "http://www.w3.org/2001/XMLSchema#integer"),
+            )
+            .quad(QueryId::Ignore, as2!(name), QueryObject::literal("naam"))
+            .quad(
+                "https://example.com/a>b c\\d",
+                QueryId::any(vec!["urn:x>y"]),
+                QueryObject::typed("x", "urn:a b"),
+            );
 
-        Ok(QuadQuery(first.parse()?, second.parse()?, third.parse()?))
+        assert_eq!(QuadQuery::parse_all(&query.to_string()), Ok(query.build()));
     }
 }
@@ -0,0 +1,247 @@
+use std::fmt;
+
+use crate::query::{QuadQuery, QueryId, QueryObject};
+
+/// The predicate used for the types of an entity in queries.
+pub const RDF_TYPE: &'static str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#type";
+
+/// A placeholder in a query, as handed out by `QueryBuilder::placeholder`.
+///  The results of a query contain the value of every placeholder, in the
+///  order of their index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Placeholder(u32);
+
+impl Placeholder {
+    pub fn new(index: u32) -> Placeholder {
+        Placeholder(index)
+    }
+
+    /// The index of this placeholder in the rows returned by the query.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl From<Placeholder> for QueryId {
+    fn from(placeholder: Placeholder) -> QueryId {
+        QueryId::Placeholder(placeholder.0)
+    }
+}
+
+impl From<Placeholder> for QueryObject {
+    fn from(placeholder: Placeholder) -> QueryObject {
+        QueryObject::Id(placeholder.into())
+    }
+}
+
+impl From<&str> for QueryId {
+    fn from(value: &str) -> QueryId {
+        QueryId::Value(value.to_owned())
+    }
+}
+
+impl From<String> for QueryId {
+    fn from(value: String) -> QueryId {
+        QueryId::Value(value)
+    }
+}
+
+impl From<QueryId> for QueryObject {
+    fn from(id: QueryId) -> QueryObject {
+        QueryObject::Id(id)
+    }
+}
+
+/// Strings are IDs when used as object. Use `QueryObject::literal` to match
+///  on a literal value.
+impl From<&str> for QueryObject {
+    fn from(value: &str) -> QueryObject {
+        QueryObject::Id(value.into())
+    }
+}
+
+impl From<String> for QueryObject {
+    fn from(value: String) -> QueryObject {
+        QueryObject::Id(value.into())
+    }
+}
+
+impl QueryId {
+    /// Matches any of the IDs passed.
+    pub fn any<I, S>(values: I) -> QueryId
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        QueryId::Any(values.into_iter().map(|f| f.into()).collect())
+    }
+}
+
+impl QueryObject {
+    /// A literal value of any type.
+    pub fn literal(value: &str) -> QueryObject {
+        QueryObject::Object {
+            value: value.to_owned(),
+            type_id: QueryId::Ignore,
+        }
+    }
+
+    /// A literal value of a specific type, e.g. `xsd:integer`.
+    pub fn typed(value: &str, type_id: impl Into<QueryId>) -> QueryObject {
+        QueryObject::Object {
+            value: value.to_owned(),
+            type_id: type_id.into(),
+        }
+    }
+
+    /// A string in a specific language.
+    pub fn language(value: &str, language: &str) -> QueryObject {
+        QueryObject::LanguageString {
+            value: value.to_owned(),
+            language: language.to_owned(),
+        }
+    }
+}
+
+/// Builds a query for `EntityStore::query`, without having to write quads by
+///  hand. Placeholders are created with `placeholder`, and can be used as
+///  subject, predicate or object of any quad added afterwards.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryBuilder {
+    quads: Vec<QuadQuery>,
+    placeholders: u32,
+}
+
+impl QueryBuilder {
+    pub fn new() -> QueryBuilder {
+        QueryBuilder {
+            quads: Vec::new(),
+            placeholders: 0,
+        }
+    }
+
+    /// Finds all objects of type `typ` attributed to `actor`. The objects are
+    ///  the first value of every row returned.
+    pub fn of_type_attributed_to(typ: &str, actor: &str) -> QueryBuilder {
+        let mut query = QueryBuilder::new();
+        let object = query.placeholder();
+        query.of_type(object, typ).attributed_to(object, actor);
+
+        query
+    }
+
+    /// Creates a new placeholder.
+    pub fn placeholder(&mut self) -> Placeholder {
+        self.placeholders += 1;
+
+        Placeholder(self.placeholders - 1)
+    }
+
+    /// Adds a quad to the query.
+    pub fn quad(
+        &mut self,
+        subject: impl Into<QueryId>,
+        predicate: impl Into<QueryId>,
+        object: impl Into<QueryObject>,
+    ) -> &mut QueryBuilder {
+        self.quads
+            .push(QuadQuery(subject.into(), predicate.into(), object.into()));
+
+        self
+    }
+
+    /// Requires `subject` to be of type `typ`.
+    pub fn of_type(&mut self, subject: impl Into<QueryId>, typ: &str) -> &mut QueryBuilder {
+        self.quad(subject, RDF_TYPE, typ)
+    }
+
+    /// Requires `subject` to be attributed to `actor`.
+    pub fn attributed_to(
+        &mut self,
+        subject: impl Into<QueryId>,
+        actor: impl Into<QueryObject>,
+    ) -> &mut QueryBuilder {
+        self.quad(subject, as2!(attributedTo), actor)
+    }
+
+    /// Requires `subject` to be a reply to `object`.
+    pub fn in_reply_to(
+        &mut self,
+        subject: impl Into<QueryId>,
+        object: impl Into<QueryObject>,
+    ) -> &mut QueryBuilder {
+        self.quad(subject, as2!(inReplyTo), object)
+    }
+
+    /// The quads of the query built so far.
+    pub fn quads(&self) -> &[QuadQuery] {
+        &self.quads
+    }
+
+    /// Returns the query, to be passed to `EntityStore::query`.
+    pub fn build(&self) -> Vec<QuadQuery> {
+        self.quads.clone()
+    }
+}
+
+/// Writes the query as a document that can be parsed by
+///  `QuadQuery::parse_all`, one quad per line.
+impl fmt::Display for QueryBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for quad in &self.quads {
+            writeln!(f, "{}", quad)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::QueryBuilder;
+    use crate::query::{QuadQuery, QueryId, QueryObject};
+
+    #[test]
+    fn builds_attributed_objects() {
+        let query = QueryBuilder::of_type_attributed_to(as2!(Note), "https://example.com/~puck");
+
+        assert_eq!(
+            Ok(query.build()),
+            QuadQuery::parse_all(
+                "?0 rdf:type as:Note\n\
+                 ?0 as:attributedTo <https://example.com/~puck>"
+            )
+        );
+    }
+
+    #[test]
+    fn builds_same_query_as_text() {
+        let mut query = QueryBuilder::new();
+        let (note, reply) = (query.placeholder(), query.placeholder());
+        query
+            .of_type(note, as2!(Note))
+            .in_reply_to(reply, note)
+            .quad(
+                reply,
+                QueryId::any(vec![as2!(content), as2!(summary)]),
+                QueryObject::language("hallo", "nl"),
+            )
+            .quad(
+                QueryId::Ignore,
+                as2!(totalItems),
+                QueryObject::typed("5", "http://www.w3.org/2001/XMLSchema#integer"),
+            )
+            .quad(reply, as2!(name), QueryObject::literal("naam"));
+
+        let text = r#"
+            ?0 rdf:type as:Note
+            ?1 as:inReplyTo ?0
+            ?1 [as:content as:summary] "hallo"@nl
+            _ as:totalItems "5"^^xsd:integer
+            ?1 as:name "naam"
+        "#;
+
+        assert_eq!(Ok(query.build()), QuadQuery::parse_all(text));
+        assert_eq!(query.quads().len(), 5);
+    }
+}
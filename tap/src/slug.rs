@@ -0,0 +1,260 @@
+/// Slugs that may not be used as a short name, as they're used for the
+///  collections and other objects the server creates itself.
+pub const RESERVED_SLUGS: &[&str] = &[
+    "activity",
+    "activities",
+    "admin",
+    "api",
+    "auth",
+    "conversation",
+    "featured",
+    "followers",
+    "following",
+    "home",
+    "inbox",
+    "liked",
+    "likes",
+    "media",
+    "oauth",
+    "objects",
+    "outbox",
+    "processed",
+    "replies",
+    "shares",
+    "static",
+    "usernames",
+    "well-known",
+];
+
+/// Checks if a slug is reserved for use by the server.
+pub fn is_reserved_slug(slug: &str) -> bool {
+    RESERVED_SLUGS.contains(&slug)
+}
+
+/// Transliterates a single lowercase character of a common non-ASCII script
+///  (Latin with diacritics, Greek, Cyrillic) to ASCII.
+fn transliterate(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĳ' => "ij",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' | 'ț' => "t",
+        'þ' => "th",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+
+        // Greek
+        'α' | 'ά' => "a",
+        'β' => "v",
+        'γ' => "g",
+        'δ' => "d",
+        'ε' | 'έ' => "e",
+        'ζ' => "z",
+        'η' | 'ή' => "i",
+        'θ' => "th",
+        'ι' | 'ί' | 'ϊ' | 'ΐ' => "i",
+        'κ' => "k",
+        'λ' => "l",
+        'μ' => "m",
+        'ν' => "n",
+        'ξ' => "x",
+        'ο' | 'ό' => "o",
+        'π' => "p",
+        'ρ' => "r",
+        'σ' | 'ς' => "s",
+        'τ' => "t",
+        'υ' | 'ύ' | 'ϋ' | 'ΰ' => "y",
+        'φ' => "f",
+        'χ' => "ch",
+        'ψ' => "ps",
+        'ω' | 'ώ' => "o",
+
+        // Cyrillic
+        'а' => "a",
+        'б' => "b",
+        'в' => "v",
+        'г' => "g",
+        'ґ' => "g",
+        'д' => "d",
+        'е' | 'ё' | 'є' => "e",
+        'ж' => "zh",
+        'з' => "z",
+        'и' | 'і' | 'ї' | 'й' => "i",
+        'ј' => "j",
+        'к' => "k",
+        'л' => "l",
+        'љ' => "lj",
+        'м' => "m",
+        'н' => "n",
+        'њ' => "nj",
+        'о' => "o",
+        'п' => "p",
+        'р' => "r",
+        'с' => "s",
+        'т' => "t",
+        'у' | 'ў' => "u",
+        'ф' => "f",
+        'х' => "kh",
+        'ц' => "ts",
+        'ч' => "ch",
+        'џ' => "dzh",
+        'ш' => "sh",
+        'щ' => "shch",
+        'ъ' | 'ь' => "",
+        'ы' => "y",
+        'э' => "e",
+        'ю' => "yu",
+        'я' => "ya",
+
+        _ => return None,
+    })
+}
+
+/// Turns arbitrary text into a slug of at most `max_len` characters, for use
+///  in an URL.
+///
+/// Common scripts are transliterated to ASCII. Other alphanumeric characters
+///  (e.g. CJK) are kept as-is, and everything else becomes a single dash.
+///  Markup tags are skipped, and the slug never starts or ends with a dash.
+///  If the text has to be cut short, it is cut at a dash if possible.
+///
+/// Returns `None` if nothing usable remains.
+pub fn slugify(text: &str, max_len: usize) -> Option<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut in_tag = false;
+
+    for ch in text.chars() {
+        if in_tag {
+            in_tag = ch != '>';
+            continue;
+        } else if ch == '<' {
+            in_tag = true;
+            continue;
+        }
+
+        for ch in ch.to_lowercase() {
+            if ch.is_ascii_alphanumeric() {
+                current.push(ch);
+            } else if let Some(latin) = transliterate(ch) {
+                current += latin;
+            } else if ch.is_alphanumeric() {
+                current.push(ch);
+            } else if !current.is_empty() {
+                parts.push(std::mem::replace(&mut current, String::new()));
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        parts.push(current);
+    }
+
+    let mut result = String::new();
+    let mut length = 0;
+
+    for part in parts {
+        let part_len = part.chars().count();
+
+        if length == 0 {
+            result = part.chars().take(max_len).collect();
+            length = result.chars().count();
+        } else if length + 1 + part_len <= max_len {
+            result.push('-');
+            result += &part;
+            length += 1 + part_len;
+        } else {
+            break;
+        }
+
+        if length >= max_len {
+            break;
+        }
+    }
+
+    if result.is_empty() {
+        None
+    } else {
+        Some(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_reserved_slug, slugify};
+    use crate::id::shortname_suggestion;
+    use jsonld::nodemap::{Entity, Pointer, Value};
+
+    #[test]
+    fn handles_non_latin_text() {
+        assert_eq!(slugify("Ελληνικά", 32), Some("ellinika".to_owned()));
+        assert_eq!(slugify("Привет, мир", 32), Some("privet-mir".to_owned()));
+        assert_eq!(slugify("Crème brûlée", 32), Some("creme-brulee".to_owned()));
+        assert_eq!(
+            slugify("日本語のテキスト", 32),
+            Some("日本語のテキスト".to_owned())
+        );
+        assert_eq!(slugify("🎉 ✨", 32), None);
+    }
+
+    #[test]
+    fn truncates_at_limit() {
+        assert_eq!(slugify("日本語のテキスト", 3), Some("日本語".to_owned()));
+        assert_eq!(
+            slugify("hello wonderful world", 16),
+            Some("hello-wonderful".to_owned())
+        );
+        assert_eq!(
+            slugify("hello wonderful world", 14),
+            Some("hello".to_owned())
+        );
+        assert_eq!(slugify("extraordinary", 5), Some("extra".to_owned()));
+    }
+
+    #[test]
+    fn collapses_separators() {
+        assert_eq!(slugify("  a -- b!!c  ", 32), Some("a-b-c".to_owned()));
+        assert_eq!(
+            slugify("<p>Hello, <b>world</b>!</p>", 32),
+            Some("hello-world".to_owned())
+        );
+        assert_eq!(slugify("---", 32), None);
+    }
+
+    #[test]
+    fn avoids_reserved_slugs() {
+        assert!(is_reserved_slug("inbox"));
+        assert!(!is_reserved_slug("inboxes"));
+
+        let mut note = Entity::new("/note".to_owned());
+        note.types.push(as2!(Note).to_owned());
+        note[as2!(name)].push(Pointer::Value(Value {
+            value: "Inbox".into(),
+            type_id: None,
+            language: None,
+        }));
+
+        assert_eq!(shortname_suggestion(&note), Some("note".to_owned()));
+
+        let mut collection = Entity::new("/followers".to_owned());
+        collection.types.push(as2!(Followers).to_owned());
+        assert_eq!(shortname_suggestion(&collection), None);
+    }
+}
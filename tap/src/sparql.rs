@@ -0,0 +1,1127 @@
+//! A parser for a practical subset of SPARQL `SELECT` queries, which are
+//!  translated to a `Query` that can be run against any `EntityStore`.
+//!
+//! Supported are `PREFIX` declarations, `SELECT` with `DISTINCT` or `*`,
+//!  basic graph patterns (including `a`, `;` and `,`), `OPTIONAL`, `MINUS`,
+//!  `FILTER NOT EXISTS`, and `FILTER` expressions that can be expressed as a
+//!  `Filter`, joined by `&&`. These are followed by `ORDER BY`, `LIMIT` and
+//!  `OFFSET`.
+
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::entitystore::{EntityStore, StoreError};
+use crate::error::{ErrorKind, TapError};
+use crate::prefix::PrefixMap;
+use crate::query::{Comparison, Filter, OrderBy, QuadQuery, Query, QueryId, QueryObject};
+use crate::querybuilder::{Placeholder, RDF_TYPE};
+
+/// A single result of a `SparqlQuery`, mapping variable names (without `?`)
+///  to their values. Variables that aren't bound are left out.
+pub type SparqlRow = BTreeMap<String, String>;
+
+/// A parsed SPARQL query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparqlQuery {
+    /// The names of the variables selected, in order.
+    pub variables: Vec<String>,
+
+    /// Whether duplicate results are removed.
+    pub distinct: bool,
+
+    /// The translated query. Variables are numbered in order of their first
+    ///  use in the `WHERE` clause.
+    pub query: Query,
+
+    placeholders: Vec<String>,
+}
+
+impl SparqlQuery {
+    /// Parses a query, using the `DEFAULT_PREFIXES` next to the prefixes
+    ///  declared in the query.
+    pub fn parse(input: &str) -> Result<SparqlQuery, SparqlParseError> {
+        SparqlQuery::parse_with(input, &PrefixMap::default())
+    }
+
+    /// Parses a query, using a specific set of prefixes next to the ones
+    ///  declared in the query.
+    pub fn parse_with(input: &str, prefixes: &PrefixMap) -> Result<SparqlQuery, SparqlParseError> {
+        Parser {
+            input,
+            tokens: tokenize(input)?,
+            index: 0,
+            prefixes: prefixes.clone(),
+            placeholders: Vec::new(),
+        }
+        .parse()
+    }
+
+    /// The placeholder a variable was translated to, if it's used in the
+    ///  query.
+    pub fn placeholder(&self, variable: &str) -> Option<Placeholder> {
+        self.placeholders
+            .iter()
+            .position(|f| f == variable)
+            .map(|f| Placeholder::new(f as u32))
+    }
+
+    /// Runs the query using `EntityStore::select`.
+    pub async fn run(&self, store: &mut dyn EntityStore) -> Result<Vec<SparqlRow>, StoreError> {
+        let mut query = self.query.clone();
+
+        // Duplicates have to be removed before the results are paginated.
+        if self.distinct {
+            query.limit = None;
+            query.offset = 0;
+        }
+
+        let rows = store.select(query).await?;
+
+        Ok(self.results(rows))
+    }
+
+    fn results(&self, rows: Vec<Vec<Option<String>>>) -> Vec<SparqlRow> {
+        let mut results: Vec<SparqlRow> = rows
+            .into_iter()
+            .map(|row| {
+                self.variables
+                    .iter()
+                    .filter_map(|name| {
+                        let value = row.get(self.placeholder(name)?.index())?.clone()?;
+
+                        Some((name.to_owned(), value))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if self.distinct {
+            let mut seen = HashSet::new();
+            results.retain(|f| seen.insert(f.clone()));
+
+            results = results
+                .into_iter()
+                .skip(self.query.offset)
+                .take(self.query.limit.unwrap_or(usize::MAX))
+                .collect();
+        }
+
+        results
+    }
+}
+
+impl FromStr for SparqlQuery {
+    type Err = SparqlParseError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SparqlQuery::parse(s)
+    }
+}
+
+/// The reason a SPARQL query couldn't be parsed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SparqlParseErrorKind {
+    /// A string literal was not closed before the end of the input.
+    UnterminatedString,
+
+    /// An IRI between `<` and `>` was not closed.
+    UnterminatedIri,
+
+    /// An unknown escape sequence was found in a literal.
+    InvalidEscape(String),
+
+    /// A character that can't start a token was found.
+    UnexpectedCharacter(char),
+
+    /// A token was found where it isn't allowed.
+    UnexpectedToken(String),
+
+    /// The query ended while more was expected.
+    UnexpectedEnd,
+
+    /// A prefixed name used a prefix that isn't declared.
+    UnknownPrefix(String),
+
+    /// A number couldn't be parsed, e.g. a negative `LIMIT`.
+    InvalidNumber(String),
+
+    /// A `FILTER` can't be translated to a `Filter`, e.g. because it uses
+    ///  `||`, or compares to something other than a number or date.
+    UnsupportedFilter,
+}
+
+impl fmt::Display for SparqlParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SparqlParseErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            SparqlParseErrorKind::UnterminatedIri => write!(f, "unterminated IRI"),
+            SparqlParseErrorKind::InvalidEscape(ref escape) => {
+                write!(f, "invalid escape sequence \\{}", escape)
+            }
+            SparqlParseErrorKind::UnexpectedCharacter(ch) => {
+                write!(f, "unexpected character {:?}", ch)
+            }
+            SparqlParseErrorKind::UnexpectedToken(ref token) => {
+                write!(f, "unexpected {}", token)
+            }
+            SparqlParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of query"),
+            SparqlParseErrorKind::UnknownPrefix(ref prefix) => {
+                write!(f, "unknown prefix {}:", prefix)
+            }
+            SparqlParseErrorKind::InvalidNumber(ref number) => {
+                write!(f, "invalid number {}", number)
+            }
+            SparqlParseErrorKind::UnsupportedFilter => write!(f, "unsupported filter"),
+        }
+    }
+}
+
+/// An error while parsing a SPARQL query, including the position it occured
+///  at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparqlParseError {
+    pub kind: SparqlParseErrorKind,
+
+    /// The line the error occured on, starting at 1.
+    pub line: usize,
+
+    /// The column the error occured on, in characters, starting at 1.
+    pub column: usize,
+
+    /// The offset in the input the error occured at, in bytes.
+    pub offset: usize,
+}
+
+impl fmt::Display for SparqlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.kind, self.line, self.column)
+    }
+}
+
+impl Error for SparqlParseError {}
+
+impl From<SparqlParseError> for TapError {
+    fn from(error: SparqlParseError) -> TapError {
+        TapError::new(ErrorKind::InvalidInput, error)
+    }
+}
+
+fn error_at(input: &str, offset: usize, kind: SparqlParseErrorKind) -> SparqlParseError {
+    let before = &input[..offset];
+
+    SparqlParseError {
+        kind,
+        line: before.matches('\n').count() + 1,
+        column: before.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+        offset,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Variable(String),
+    Iri(String),
+
+    /// A keyword or prefixed name.
+    Name(String),
+
+    Literal {
+        value: String,
+        language: Option<String>,
+    },
+
+    Number(String),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Variable(ref name) => write!(f, "?{}", name),
+            Token::Iri(ref iri) => write!(f, "<{}>", iri),
+            Token::Name(ref name) | Token::Number(ref name) => write!(f, "{}", name),
+            Token::Literal { ref value, .. } => write!(f, "{:?}", value),
+            Token::Symbol(symbol) => write!(f, "{}", symbol),
+        }
+    }
+}
+
+const SYMBOLS: &'static [&'static str] = &[
+    "<=", ">=", "!=", "&&", "||", "^^", "{", "}", "(", ")", ".", ";", ",", "*", "=", "<", ">",
+];
+
+/// Reads a string literal, returning it and its length in bytes.
+fn string(input: &str, start: usize) -> Result<(Token, usize), SparqlParseError> {
+    let rest = &input[start..];
+    let quote = rest.chars().next().unwrap();
+
+    let mut value = String::new();
+    let mut chars = rest.char_indices().skip(1);
+    let end = loop {
+        let (offset, ch) = chars
+            .next()
+            .ok_or_else(|| error_at(input, start, SparqlParseErrorKind::UnterminatedString))?;
+
+        if ch == quote {
+            break offset + 1;
+        } else if ch != '\\' {
+            value.push(ch);
+            continue;
+        }
+
+        let escape = |escape: &str| {
+            error_at(
+                input,
+                start + offset,
+                SparqlParseErrorKind::InvalidEscape(escape.to_owned()),
+            )
+        };
+
+        value.push(match chars.next() {
+            Some((_, 't')) => '\t',
+            Some((_, 'n')) => '\n',
+            Some((_, 'r')) => '\r',
+            Some((_, '"')) => '"',
+            Some((_, '\'')) => '\'',
+            Some((_, '\\')) => '\\',
+            Some((_, ch)) if ch == 'u' || ch == 'U' => {
+                let len = if ch == 'u' { 4 } else { 8 };
+                let hex: String = (&mut chars).take(len).map(|(_, f)| f).collect();
+
+                u32::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| hex.len() == len)
+                    .and_then(std::char::from_u32)
+                    .ok_or_else(|| escape(&format!("{}{}", ch, hex)))?
+            }
+            Some((_, ch)) => return Err(escape(&ch.to_string())),
+            None => {
+                return Err(error_at(
+                    input,
+                    start,
+                    SparqlParseErrorKind::UnterminatedString,
+                ))
+            }
+        });
+    };
+
+    let language = if rest[end..].starts_with('@') {
+        let len = rest[end + 1..]
+            .find(|f: char| !(f.is_ascii_alphanumeric() || f == '-'))
+            .unwrap_or(rest.len() - end - 1);
+
+        Some(rest[end + 1..end + 1 + len].to_owned())
+    } else {
+        None
+    };
+
+    let len = end + language.as_ref().map(|f| f.len() + 1).unwrap_or(0);
+
+    Ok((Token::Literal { value, language }, len))
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, SparqlParseError> {
+    let mut tokens = Vec::new();
+    let mut offset = 0;
+
+    while let Some(ch) = input[offset..].chars().next() {
+        let rest = &input[offset..];
+
+        if ch.is_whitespace() {
+            offset += ch.len_utf8();
+            continue;
+        } else if ch == '#' {
+            offset += rest.find('\n').unwrap_or(rest.len());
+            continue;
+        }
+
+        let next = rest[ch.len_utf8()..].chars().next();
+        let (token, len) = match ch {
+            '?' | '$' => {
+                let len = rest[1..]
+                    .find(|f: char| !(f.is_alphanumeric() || f == '_'))
+                    .unwrap_or(rest.len() - 1);
+
+                if len == 0 {
+                    return Err(error_at(
+                        input,
+                        offset,
+                        SparqlParseErrorKind::UnexpectedCharacter(ch),
+                    ));
+                }
+
+                (Token::Variable(rest[1..=len].to_owned()), len + 1)
+            }
+
+            '"' | '\'' => string(input, offset)?,
+
+            // `<` is also used for comparisons, so it only starts an IRI if
+            //  the IRI is closed before any whitespace.
+            '<' if next
+                .map(|f| f != '=' && !f.is_whitespace())
+                .unwrap_or(false) =>
+            {
+                match rest.find(|f: char| f == '>' || f.is_whitespace()) {
+                    Some(end) if rest[end..].starts_with('>') => {
+                        (Token::Iri(rest[1..end].to_owned()), end + 1)
+                    }
+                    _ if next.map(|f| f.is_alphabetic()).unwrap_or(false) => {
+                        return Err(error_at(
+                            input,
+                            offset,
+                            SparqlParseErrorKind::UnterminatedIri,
+                        ))
+                    }
+                    _ => (Token::Symbol("<"), 1),
+                }
+            }
+
+            ch if ch.is_ascii_digit()
+                || ((ch == '-' || ch == '+')
+                    && next.map(|f| f.is_ascii_digit()).unwrap_or(false)) =>
+            {
+                let mut len = 1 + rest[1..]
+                    .find(|f: char| !f.is_ascii_digit())
+                    .unwrap_or(rest.len() - 1);
+
+                // A `.` is only part of the number if a digit follows.
+                let fraction = &rest[len..];
+                if fraction.starts_with('.')
+                    && fraction[1..].starts_with(|f: char| f.is_ascii_digit())
+                {
+                    len += 1 + fraction[1..]
+                        .find(|f: char| !f.is_ascii_digit())
+                        .unwrap_or(fraction.len() - 1);
+                }
+
+                (Token::Number(rest[..len].to_owned()), len)
+            }
+
+            ch if ch.is_alphanumeric() || ch == '_' || ch == ':' => {
+                let word = rest
+                    .find(|f: char| !(f.is_alphanumeric() || "_-:.%".contains(f)))
+                    .map(|f| &rest[..f])
+                    .unwrap_or(rest);
+
+                // A `.` at the end of a name ends the pattern.
+                let word = word.trim_end_matches('.');
+
+                (Token::Name(word.to_owned()), word.len())
+            }
+
+            ch => match SYMBOLS.iter().find(|f| rest.starts_with(*f)) {
+                Some(symbol) => (Token::Symbol(symbol), symbol.len()),
+                None => {
+                    return Err(error_at(
+                        input,
+                        offset,
+                        SparqlParseErrorKind::UnexpectedCharacter(ch),
+                    ))
+                }
+            },
+        };
+
+        tokens.push((offset, token));
+        offset += len;
+    }
+
+    Ok(tokens)
+}
+
+/// The comparison to use if both sides of a comparison are swapped.
+fn flip(comparison: Comparison) -> Comparison {
+    match comparison {
+        Comparison::Less => Comparison::Greater,
+        Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+        Comparison::Greater => Comparison::Less,
+        Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+        comparison => comparison,
+    }
+}
+
+/// A value a variable is compared to in a filter.
+enum Operand {
+    Number(f64),
+    Date(DateTime<Utc>),
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    prefixes: PrefixMap,
+    placeholders: Vec<String>,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, kind: SparqlParseErrorKind) -> SparqlParseError {
+        let offset = match self.tokens.get(self.index) {
+            Some((offset, _)) => *offset,
+            None => self.input.len(),
+        };
+
+        error_at(self.input, offset, kind)
+    }
+
+    fn unexpected(&self) -> SparqlParseError {
+        self.error(match self.peek() {
+            Some(token) => SparqlParseErrorKind::UnexpectedToken(token.to_string()),
+            None => SparqlParseErrorKind::UnexpectedEnd,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        match self.peek() {
+            Some(Token::Symbol(found)) if *found == symbol => {
+                self.index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), SparqlParseError> {
+        if self.eat(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Reads a keyword, which is case insensitive.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Name(name)) if name.eq_ignore_ascii_case(keyword) => {
+                self.index += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), SparqlParseError> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    fn placeholder(&mut self, name: &str) -> Placeholder {
+        let index = match self.placeholders.iter().position(|f| f == name) {
+            Some(index) => index,
+            None => {
+                self.placeholders.push(name.to_owned());
+                self.placeholders.len() - 1
+            }
+        };
+
+        Placeholder::new(index as u32)
+    }
+
+    fn variable(&mut self) -> Result<Placeholder, SparqlParseError> {
+        let name = match self.peek() {
+            Some(Token::Variable(name)) => name.to_owned(),
+            _ => return Err(self.unexpected()),
+        };
+
+        self.index += 1;
+        Ok(self.placeholder(&name))
+    }
+
+    fn number<T: FromStr>(&mut self) -> Result<T, SparqlParseError> {
+        let number = match self.peek() {
+            Some(Token::Number(number)) => number.to_owned(),
+            _ => return Err(self.unexpected()),
+        };
+
+        let result = number
+            .parse()
+            .map_err(|_| self.error(SparqlParseErrorKind::InvalidNumber(number)))?;
+
+        self.index += 1;
+        Ok(result)
+    }
+
+    fn string(&mut self) -> Result<String, SparqlParseError> {
+        let value = match self.peek() {
+            Some(Token::Literal { value, .. }) => value.to_owned(),
+            _ => return Err(self.unexpected()),
+        };
+
+        self.index += 1;
+        Ok(value)
+    }
+
+    fn expand(&self, name: &str) -> Result<String, SparqlParseError> {
+        match self.prefixes.expand(name) {
+            Some(iri) => Ok(iri),
+            None => {
+                let prefix = name.split(':').next().unwrap_or(name);
+
+                Err(self.error(SparqlParseErrorKind::UnknownPrefix(prefix.to_owned())))
+            }
+        }
+    }
+
+    /// Reads a variable, IRI or prefixed name.
+    fn id(&mut self) -> Result<QueryId, SparqlParseError> {
+        let id = match self.peek() {
+            Some(Token::Variable(_)) => return Ok(self.variable()?.into()),
+            Some(Token::Iri(iri)) => QueryId::Value(iri.to_owned()),
+            Some(Token::Name(name)) if name.contains(':') => QueryId::Value(self.expand(name)?),
+            _ => return Err(self.unexpected()),
+        };
+
+        self.index += 1;
+        Ok(id)
+    }
+
+    fn verb(&mut self) -> Result<QueryId, SparqlParseError> {
+        if self.peek() == Some(&Token::Name("a".to_owned())) {
+            self.index += 1;
+            Ok(RDF_TYPE.into())
+        } else {
+            self.id()
+        }
+    }
+
+    fn object(&mut self) -> Result<QueryObject, SparqlParseError> {
+        let value = match self.peek() {
+            Some(Token::Literal {
+                value,
+                language: Some(language),
+            }) => {
+                let object = QueryObject::language(value, language);
+                self.index += 1;
+                return Ok(object);
+            }
+
+            Some(Token::Literal { value, .. }) | Some(Token::Number(value)) => value.to_owned(),
+            Some(Token::Name(name)) if name == "true" || name == "false" => name.to_owned(),
+            _ => return Ok(self.id()?.into()),
+        };
+
+        self.index += 1;
+        if self.eat("^^") {
+            Ok(QueryObject::typed(&value, self.id()?))
+        } else {
+            Ok(QueryObject::literal(&value))
+        }
+    }
+
+    /// Reads the patterns for a single subject, e.g. `?a a as:Note ; as:to ?b, ?c`.
+    fn triples(&mut self, patterns: &mut Vec<QuadQuery>) -> Result<(), SparqlParseError> {
+        let subject = self.id()?;
+
+        loop {
+            let predicate = self.verb()?;
+
+            loop {
+                let object = self.object()?;
+                patterns.push(QuadQuery(subject.clone(), predicate.clone(), object));
+
+                if !self.eat(",") {
+                    break;
+                }
+            }
+
+            if !self.eat(";") {
+                break;
+            }
+
+            while self.eat(";") {}
+
+            match self.peek() {
+                Some(Token::Symbol(".")) | Some(Token::Symbol("}")) => break,
+                _ => continue,
+            }
+        }
+
+        // Patterns are separated by `.`, which is optional before `}` and
+        //  the keywords that can follow them.
+        match self.peek() {
+            Some(Token::Symbol(".")) | Some(Token::Symbol("}")) => Ok(()),
+            Some(Token::Name(name))
+                if ["OPTIONAL", "MINUS", "FILTER"]
+                    .iter()
+                    .any(|f| name.eq_ignore_ascii_case(f)) =>
+            {
+                Ok(())
+            }
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    /// Reads a group that may only contain patterns, e.g. in `OPTIONAL`.
+    fn block(&mut self) -> Result<Vec<QuadQuery>, SparqlParseError> {
+        self.expect("{")?;
+
+        let mut patterns = Vec::new();
+        while !self.eat("}") {
+            if !self.eat(".") {
+                self.triples(&mut patterns)?;
+            }
+        }
+
+        Ok(patterns)
+    }
+
+    fn group(&mut self, query: &mut Query) -> Result<(), SparqlParseError> {
+        self.expect("{")?;
+
+        while !self.eat("}") {
+            if self.eat(".") {
+                continue;
+            } else if self.keyword("OPTIONAL") {
+                query.optional.push(self.block()?);
+            } else if self.keyword("MINUS") {
+                query.not.push(self.block()?);
+            } else if self.keyword("FILTER") {
+                if self.keyword("NOT") {
+                    self.expect_keyword("EXISTS")?;
+                    query.not.push(self.block()?);
+                } else {
+                    self.condition(&mut query.filters)?;
+                }
+            } else {
+                self.triples(&mut query.patterns)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn conjunction(&mut self, filters: &mut Vec<Filter>) -> Result<(), SparqlParseError> {
+        loop {
+            self.condition(filters)?;
+
+            if self.peek() == Some(&Token::Symbol("||")) {
+                return Err(self.error(SparqlParseErrorKind::UnsupportedFilter));
+            } else if !self.eat("&&") {
+                return Ok(());
+            }
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Comparison, SparqlParseError> {
+        let comparison = match self.peek() {
+            Some(Token::Symbol("=")) => Comparison::Equal,
+            Some(Token::Symbol("!=")) => Comparison::NotEqual,
+            Some(Token::Symbol("<")) => Comparison::Less,
+            Some(Token::Symbol("<=")) => Comparison::LessOrEqual,
+            Some(Token::Symbol(">")) => Comparison::Greater,
+            Some(Token::Symbol(">=")) => Comparison::GreaterOrEqual,
+            _ => return Err(self.unexpected()),
+        };
+
+        self.index += 1;
+        Ok(comparison)
+    }
+
+    fn operand(&mut self) -> Result<Operand, SparqlParseError> {
+        match self.peek() {
+            Some(Token::Number(_)) => Ok(Operand::Number(self.number()?)),
+            Some(Token::Literal { language: None, .. }) => {
+                let error = self.error(SparqlParseErrorKind::UnsupportedFilter);
+                let value = self.string()?;
+                if self.eat("^^") {
+                    self.id()?;
+                }
+
+                DateTime::parse_from_rfc3339(&value)
+                    .map(|f| Operand::Date(f.with_timezone(&Utc)))
+                    .map_err(|_| error)
+            }
+            _ => Err(self.error(SparqlParseErrorKind::UnsupportedFilter)),
+        }
+    }
+
+    /// Reads a single condition of a `FILTER`, adding it to `filters`.
+    fn condition(&mut self, filters: &mut Vec<Filter>) -> Result<(), SparqlParseError> {
+        if self.eat("(") {
+            self.conjunction(filters)?;
+            return self.expect(")");
+        }
+
+        if self.keyword("STRSTARTS") {
+            self.expect("(")?;
+            let placeholder = if self.keyword("STR") {
+                self.expect("(")?;
+                let placeholder = self.variable()?;
+                self.expect(")")?;
+                placeholder
+            } else {
+                self.variable()?
+            };
+
+            self.expect(",")?;
+            let prefix = self.string()?;
+            self.expect(")")?;
+
+            filters.push(Filter::StartsWith(placeholder, prefix));
+            return Ok(());
+        }
+
+        if self.keyword("LANGMATCHES") {
+            self.expect("(")?;
+            self.expect_keyword("LANG")?;
+            self.expect("(")?;
+            let placeholder = self.variable()?;
+            self.expect(")")?;
+            self.expect(",")?;
+            let language = self.string()?;
+            self.expect(")")?;
+
+            filters.push(Filter::Language(placeholder, language));
+            return Ok(());
+        }
+
+        let (placeholder, comparison, operand) = match self.peek() {
+            Some(Token::Variable(_)) => {
+                let placeholder = self.variable()?;
+                let comparison = self.comparison()?;
+                (placeholder, comparison, self.operand()?)
+            }
+
+            _ => {
+                let operand = self.operand()?;
+                let comparison = flip(self.comparison()?);
+                (self.variable()?, comparison, operand)
+            }
+        };
+
+        filters.push(match operand {
+            Operand::Number(number) => Filter::Number(placeholder, comparison, number),
+            Operand::Date(date) => Filter::Date(placeholder, comparison, date),
+        });
+
+        Ok(())
+    }
+
+    fn modifiers(&mut self, query: &mut Query) -> Result<(), SparqlParseError> {
+        if self.keyword("ORDER") {
+            self.expect_keyword("BY")?;
+
+            loop {
+                let descending = if self.keyword("ASC") {
+                    false
+                } else if self.keyword("DESC") {
+                    true
+                } else if let Some(Token::Variable(_)) = self.peek() {
+                    let placeholder = self.variable()?;
+                    query.order.push(OrderBy {
+                        placeholder,
+                        descending: false,
+                    });
+
+                    continue;
+                } else {
+                    break;
+                };
+
+                self.expect("(")?;
+                let placeholder = self.variable()?;
+                self.expect(")")?;
+
+                query.order.push(OrderBy {
+                    placeholder,
+                    descending,
+                });
+            }
+
+            if query.order.is_empty() {
+                return Err(self.unexpected());
+            }
+        }
+
+        loop {
+            if self.keyword("LIMIT") {
+                query.limit = Some(self.number()?);
+            } else if self.keyword("OFFSET") {
+                query.offset = self.number()?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse(mut self) -> Result<SparqlQuery, SparqlParseError> {
+        while self.keyword("PREFIX") {
+            let prefix = match self.peek() {
+                Some(Token::Name(name)) if name.ends_with(':') => name[..name.len() - 1].to_owned(),
+                _ => return Err(self.unexpected()),
+            };
+
+            self.index += 1;
+            let namespace = match self.peek() {
+                Some(Token::Iri(iri)) => iri.to_owned(),
+                _ => return Err(self.unexpected()),
+            };
+
+            self.index += 1;
+            self.prefixes.insert(&prefix, &namespace);
+        }
+
+        self.expect_keyword("SELECT")?;
+        let distinct = self.keyword("DISTINCT");
+
+        let selected = if self.eat("*") {
+            None
+        } else {
+            let mut variables = Vec::new();
+            while let Some(Token::Variable(name)) = self.peek() {
+                variables.push(name.to_owned());
+                self.index += 1;
+            }
+
+            if variables.is_empty() {
+                return Err(self.unexpected());
+            }
+
+            Some(variables)
+        };
+
+        self.keyword("WHERE");
+
+        let mut query = Query::default();
+        self.group(&mut query)?;
+        self.modifiers(&mut query)?;
+
+        if self.peek().is_some() {
+            return Err(self.unexpected());
+        }
+
+        let variables = match selected {
+            Some(variables) => variables,
+            None => in_scope(&query)
+                .into_iter()
+                .map(|f| self.placeholders[f as usize].to_owned())
+                .collect(),
+        };
+
+        Ok(SparqlQuery {
+            variables,
+            distinct,
+            query,
+            placeholders: self.placeholders,
+        })
+    }
+}
+
+/// The placeholders bound by the patterns and optional patterns of a query,
+///  which are the ones returned by `SELECT *`.
+fn in_scope(query: &Query) -> BTreeSet<u32> {
+    let mut placeholders = BTreeSet::new();
+
+    for QuadQuery(subject, predicate, object) in
+        query.patterns.iter().chain(query.optional.iter().flatten())
+    {
+        let object = match object {
+            QueryObject::Id(id) => Some(id),
+            QueryObject::Object { type_id, .. } => Some(type_id),
+            QueryObject::LanguageString { .. } => None,
+        };
+
+        for id in vec![subject, predicate].into_iter().chain(object) {
+            if let QueryId::Placeholder(placeholder) = id {
+                placeholders.insert(*placeholder);
+            }
+        }
+    }
+
+    placeholders
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SparqlParseErrorKind, SparqlQuery};
+    use crate::evaluate::{evaluate_query, Quad, Term};
+    use crate::query::{Comparison, Filter, OrderBy, QuadQuery, QueryId, QueryObject};
+    use crate::querybuilder::{Placeholder, RDF_TYPE};
+
+    fn value(s: &str) -> QueryId {
+        QueryId::Value(s.to_owned())
+    }
+
+    #[test]
+    fn parses_patterns() {
+        let query = SparqlQuery::parse(
+            "PREFIX ex: <https://example.com/>
+             SELECT ?note ?content WHERE {
+                 ?note a as:Note ;
+                     as:content ?content ;
+                     as:to ex:puck, ex:ariel .
+             }",
+        )
+        .unwrap();
+
+        let note = QueryId::Placeholder(0);
+        assert_eq!(query.variables, vec!["note", "content"]);
+        assert_eq!(
+            query.query.patterns,
+            vec![
+                QuadQuery(
+                    note.clone(),
+                    value(RDF_TYPE),
+                    QueryObject::Id(value(as2!(Note)))
+                ),
+                QuadQuery(
+                    note.clone(),
+                    value(as2!(content)),
+                    QueryObject::Id(QueryId::Placeholder(1))
+                ),
+                QuadQuery(
+                    note.clone(),
+                    value(as2!(to)),
+                    QueryObject::Id(value("https://example.com/puck"))
+                ),
+                QuadQuery(
+                    note,
+                    value(as2!(to)),
+                    QueryObject::Id(value("https://example.com/ariel"))
+                ),
+            ]
+        );
+        assert!(query.query.is_basic());
+    }
+
+    #[test]
+    fn parses_modifiers() {
+        let query = SparqlQuery::parse(
+            "SELECT DISTINCT * {
+                 ?note as:published ?published .
+                 OPTIONAL { ?note as:name ?name }
+                 MINUS { ?note as:sensitive true }
+                 FILTER (?published >= \"2019-01-01T00:00:00Z\"^^xsd:dateTime && 10 > ?count)
+                 FILTER STRSTARTS(STR(?note), \"https://example.com/\")
+                 FILTER LANGMATCHES(LANG(?name), \"en\")
+             }
+             ORDER BY DESC(?published) ?note
+             LIMIT 10 OFFSET 20",
+        )
+        .unwrap();
+
+        let (note, published, name, count) = (
+            Placeholder::new(0),
+            Placeholder::new(1),
+            Placeholder::new(2),
+            Placeholder::new(3),
+        );
+
+        assert!(query.distinct);
+        assert_eq!(query.variables, vec!["note", "published", "name"]);
+        assert_eq!(query.placeholder("count"), Some(count));
+        assert_eq!(query.query.optional.len(), 1);
+        assert_eq!(
+            query.query.not,
+            vec![vec![QuadQuery(
+                note.into(),
+                value(as2!(sensitive)),
+                QueryObject::literal("true")
+            )]]
+        );
+        assert_eq!(
+            query.query.filters,
+            vec![
+                Filter::Date(
+                    published,
+                    Comparison::GreaterOrEqual,
+                    "2019-01-01T00:00:00Z".parse().unwrap()
+                ),
+                Filter::Number(count, Comparison::Less, 10.0),
+                Filter::StartsWith(note, "https://example.com/".to_owned()),
+                Filter::Language(name, "en".to_owned()),
+            ]
+        );
+        assert_eq!(
+            query.query.order,
+            vec![
+                OrderBy {
+                    placeholder: published,
+                    descending: true
+                },
+                OrderBy {
+                    placeholder: note,
+                    descending: false
+                },
+            ]
+        );
+        assert_eq!((query.query.limit, query.query.offset), (Some(10), 20));
+    }
+
+    #[test]
+    fn reports_position() {
+        let error = SparqlQuery::parse("SELECT ?a {\n  ?a a foo:Bar }").unwrap_err();
+        assert_eq!(
+            error.kind,
+            SparqlParseErrorKind::UnknownPrefix("foo".to_owned())
+        );
+        assert_eq!((error.line, error.column, error.offset), (2, 8, 19));
+
+        let error =
+            SparqlQuery::parse("SELECT ?a { ?a a ?b FILTER(?b > 1 || ?b < 0) }").unwrap_err();
+        assert_eq!(error.kind, SparqlParseErrorKind::UnsupportedFilter);
+        assert_eq!(error.column, 35);
+
+        let error = SparqlQuery::parse("SELECT ?a { ?a a ?b").unwrap_err();
+        assert_eq!(error.kind, SparqlParseErrorKind::UnexpectedEnd);
+    }
+
+    #[test]
+    fn names_results() {
+        let literal = |value: &str| Term::Literal {
+            value: value.to_owned(),
+            type_id: None,
+            language: None,
+        };
+
+        let quads: Vec<_> = vec![
+            ("/a", as2!(attributedTo), Term::Id("/puck".to_owned())),
+            ("/a", as2!(name), literal("first")),
+            ("/b", as2!(attributedTo), Term::Id("/puck".to_owned())),
+            ("/c", as2!(attributedTo), Term::Id("/ariel".to_owned())),
+        ]
+        .into_iter()
+        .map(|(subject, predicate, object)| Quad {
+            subject: subject.to_owned(),
+            predicate: predicate.to_owned(),
+            object,
+        })
+        .collect();
+
+        let query = SparqlQuery::parse(
+            "SELECT ?object ?name {
+                 ?object as:attributedTo </puck> .
+                 OPTIONAL { ?object as:name ?name }
+             } ORDER BY ?object",
+        )
+        .unwrap();
+
+        let results = query.results(evaluate_query(&query.query, &quads));
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["object"], "/a");
+        assert_eq!(results[0]["name"], "first");
+        assert_eq!(results[1]["object"], "/b");
+        assert!(!results[1].contains_key("name"));
+
+        let query = SparqlQuery::parse(
+            "SELECT DISTINCT ?actor { ?object as:attributedTo ?actor } ORDER BY ?actor LIMIT 1 OFFSET 1",
+        )
+        .unwrap();
+
+        let mut all = query.query.clone();
+        all.limit = None;
+        all.offset = 0;
+
+        let results = query.results(evaluate_query(&all, &quads));
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0]["actor"], "/puck");
+    }
+}
@@ -0,0 +1,201 @@
+//! An in-memory store to test against.
+
+use crate::entity::StoreItem;
+use crate::entitystore::{CollectionPointer, EntityStore, QueueStore, StoreError};
+use crate::evaluate::{evaluate_query, item_quads};
+use crate::idstrategy::HierarchicalIds;
+use crate::query::{QuadQuery, Query};
+use crate::user::{Context, User};
+
+use jsonld::nodemap::{Entity, Pointer};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// The IDs to add to each predicate of an entity built by `entity`.
+pub type Pairs<'a> = &'a [(&'a str, &'a [&'a str])];
+
+/// Builds an entity with the given types, pointing to other IDs through `pairs`.
+pub fn entity(id: &str, types: &[&str], pairs: Pairs) -> Entity {
+    let mut entity = Entity::new(id.to_owned());
+    entity.types.extend(types.iter().map(|f| f.to_string()));
+    for (key, values) in pairs {
+        entity
+            .get_mut(key)
+            .extend(values.iter().map(|f| Pointer::Id(f.to_string())));
+    }
+
+    entity
+}
+
+/// Wraps an entity in a `StoreItem` of its own.
+pub fn item(entity: Entity) -> StoreItem {
+    let mut map = HashMap::new();
+    let id = entity.id.to_owned();
+    map.insert(id.to_owned(), entity);
+
+    StoreItem::new(id, map)
+}
+
+#[derive(Debug, Default)]
+pub struct TestStore {
+    data: HashMap<String, StoreItem>,
+    items: HashMap<String, Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl EntityStore for TestStore {
+    async fn get(&mut self, path: String, _local: bool) -> Result<Option<StoreItem>, StoreError> {
+        Ok(self.data.get(&path).cloned())
+    }
+
+    async fn put(&mut self, path: String, item: &mut StoreItem) -> Result<(), StoreError> {
+        self.data.insert(path, item.clone());
+
+        Ok(())
+    }
+
+    async fn query(&mut self, query: Vec<QuadQuery>) -> Result<Vec<Vec<String>>, StoreError> {
+        let rows = self.select(Query::new(query)).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_iter().map(|f| f.unwrap_or_default()).collect())
+            .collect())
+    }
+
+    async fn select(&mut self, query: Query) -> Result<Vec<Vec<Option<String>>>, StoreError> {
+        let quads: Vec<_> = self.data.values().flat_map(item_quads).collect();
+
+        Ok(evaluate_query(&query, &quads))
+    }
+
+    /// Collections are read newest first, and cursors are offsets into the collection.
+    async fn read_collection(
+        &mut self,
+        path: String,
+        count: Option<u32>,
+        cursor: Option<String>,
+    ) -> Result<CollectionPointer, StoreError> {
+        let items: Vec<_> = match self.items.get(&path) {
+            Some(items) => items.iter().rev().cloned().collect(),
+            None => vec![],
+        };
+
+        let count = count.map(|f| f as usize).unwrap_or_else(|| items.len());
+        let start = match cursor {
+            None => 0,
+            Some(cursor) => cursor.parse().map_err(|_| "invalid cursor")?,
+        };
+
+        let start = start.min(items.len());
+        let end = (start + count).min(items.len());
+
+        Ok(CollectionPointer {
+            items: items[start..end].to_vec(),
+            after: if end < items.len() {
+                Some(end.to_string())
+            } else {
+                None
+            },
+            before: if start > 0 {
+                Some(start.saturating_sub(count).to_string())
+            } else {
+                None
+            },
+            count: Some(items.len() as u32),
+        })
+    }
+
+    async fn find_collection(
+        &mut self,
+        path: String,
+        item: String,
+    ) -> Result<CollectionPointer, StoreError> {
+        let found = self.contains(&path, &item);
+
+        Ok(CollectionPointer {
+            items: if found { vec![item] } else { vec![] },
+            after: None,
+            before: None,
+            count: None,
+        })
+    }
+
+    async fn read_collection_inverse(
+        &mut self,
+        _item: String,
+    ) -> Result<CollectionPointer, StoreError> {
+        Err("not implemented".into())
+    }
+
+    async fn insert_collection(&mut self, path: String, item: String) -> Result<(), StoreError> {
+        let list = self.items.entry(path).or_default();
+        if !list.contains(&item) {
+            list.push(item);
+        }
+
+        Ok(())
+    }
+
+    async fn remove_collection(&mut self, path: String, item: String) -> Result<(), StoreError> {
+        if let Some(list) = self.items.get_mut(&path) {
+            list.retain(|f| f != &item);
+        }
+
+        Ok(())
+    }
+}
+
+impl TestStore {
+    pub fn new(data: Vec<Entity>) -> TestStore {
+        TestStore {
+            data: data
+                .into_iter()
+                .map(|f| (f.id.to_owned(), item(f)))
+                .collect(),
+            items: HashMap::new(),
+        }
+    }
+
+    /// Adds `items` to the collection at `path`, oldest first.
+    pub fn with_collection(mut self, path: &str, items: &[&str]) -> TestStore {
+        self.items.insert(
+            path.to_owned(),
+            items.iter().map(|f| f.to_string()).collect(),
+        );
+
+        self
+    }
+
+    pub fn contains(&self, path: &str, item: &str) -> bool {
+        self.items
+            .get(path)
+            .map(|f| f.iter().any(|v| v == item))
+            .unwrap_or(false)
+    }
+
+    /// A context for the user `subject`.
+    pub fn context<'a, 'b>(
+        &'a mut self,
+        queue: &'b mut dyn QueueStore,
+        subject: &str,
+    ) -> Context<'a, 'b> {
+        Context {
+            user: User {
+                claims: HashMap::new(),
+                issuer: None,
+                subject: subject.to_owned(),
+                audience: vec![],
+                token_identifier: "test".to_owned(),
+            },
+
+            server_base: "".to_owned(),
+            name: String::new(),
+            description: String::new(),
+            instance_id: 1,
+            id_strategy: Arc::new(HierarchicalIds),
+            entity_store: self,
+            queue_store: queue,
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::entitystore::{EntityStore, QueueStore};
+use crate::idstrategy::IdStrategy;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Context for an ActivityPub request.
 #[derive(Debug)]
@@ -24,6 +26,9 @@ pub struct Context<'a, 'b> {
 
     /// Instance ID, allows for multiple servers to share a database.
     pub instance_id: u32,
+
+    /// The strategy used to assign IDs to new entities.
+    pub id_strategy: Arc<dyn IdStrategy>,
 }
 
 /// The authorization data for a single request.
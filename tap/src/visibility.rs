@@ -0,0 +1,205 @@
+//! Classifies entities by the audience they are addressed to.
+
+use jsonld::nodemap::{Entity, Pointer};
+
+use crate::entity::StoreItem;
+use crate::entitystore::StoreError;
+use crate::user::Context;
+
+/// The visibility of an entity, derived from its addressing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Addressed to `as:Public` directly. Shows up in public collections.
+    Public,
+
+    /// Addressed to `as:Public` only through `cc` or `bcc`. Visible to anyone, but
+    /// kept out of public collections.
+    Unlisted,
+
+    /// Addressed to the followers of the author, but not to `as:Public`.
+    FollowersOnly,
+
+    /// Addressed to other collections than the followers of the author.
+    Limited,
+
+    /// Addressed to specific actors only.
+    Direct,
+}
+
+impl Visibility {
+    /// Returns true if anyone may see the entity.
+    pub fn is_public(self) -> bool {
+        self == Visibility::Public || self == Visibility::Unlisted
+    }
+
+    /// Classifies an entity, given the followers collections of its authors.
+    ///
+    /// This can't tell apart limited and direct entities, as that needs
+    /// knowledge of what the addressees are; these are all classified as direct.
+    /// Entities without any addressing are direct too, as nobody is addressed.
+    pub fn of(entity: &Entity, followers: &[String]) -> Visibility {
+        let public = Pointer::Id(as2!(Public).to_owned());
+
+        for predicate in &[as2!(to), as2!(bto), as2!(audience)] {
+            if entity[predicate].contains(&public) {
+                return Visibility::Public;
+            }
+        }
+
+        for predicate in &[as2!(cc), as2!(bcc)] {
+            if entity[predicate].contains(&public) {
+                return Visibility::Unlisted;
+            }
+        }
+
+        if addressees(entity).iter().any(|f| followers.contains(f)) {
+            Visibility::FollowersOnly
+        } else {
+            Visibility::Direct
+        }
+    }
+}
+
+/// Returns all the IDs an entity is addressed to.
+pub fn addressees(entity: &Entity) -> Vec<String> {
+    let mut result = Vec::new();
+
+    for predicate in &[as2!(to), as2!(cc), as2!(bto), as2!(bcc), as2!(audience)] {
+        for pointer in &entity[predicate] {
+            if let Pointer::Id(id) = pointer {
+                if !result.contains(id) {
+                    result.push(id.to_owned());
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// Classifies the visibility of a `StoreItem`, looking up the followers collections
+///  of its authors, and the addressees where needed.
+pub async fn visibility(
+    context: &mut Context<'_, '_>,
+    item: &StoreItem,
+) -> Result<Visibility, StoreError> {
+    let main = item.main();
+    let quick = Visibility::of(main, &[]);
+    if quick.is_public() {
+        return Ok(quick);
+    }
+
+    let mut followers = Vec::new();
+    for predicate in &[as2!(actor), as2!(attributedTo)] {
+        for author in &main[predicate] {
+            let author = match author {
+                Pointer::Id(author) => author,
+                _ => continue,
+            };
+
+            if let Some(author) = context.entity_store.get(author.to_owned(), false).await? {
+                for pointer in &author.main()[as2!(followers)] {
+                    if let Pointer::Id(id) = pointer {
+                        followers.push(id.to_owned());
+                    }
+                }
+            }
+        }
+    }
+
+    let visibility = Visibility::of(main, &followers);
+    if visibility != Visibility::Direct {
+        return Ok(visibility);
+    }
+
+    // Anything addressed to a collection isn't a direct message.
+    for addressee in addressees(main) {
+        if let Some(addressee) = context.entity_store.get(addressee, false).await? {
+            if addressee
+                .main()
+                .types
+                .iter()
+                .any(|f| f == as2!(Collection) || f == as2!(OrderedCollection))
+            {
+                return Ok(Visibility::Limited);
+            }
+        }
+    }
+
+    Ok(Visibility::Direct)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{visibility, Visibility};
+    use crate::test::{entity, item, Pairs, TestStore};
+    use async_std::task::block_on;
+    use jsonld::nodemap::Entity;
+
+    fn classify(entity: Entity, store: Vec<Entity>) -> Visibility {
+        let mut store = TestStore::new(store);
+        let mut queue = ();
+        let mut context = store.context(&mut queue, "/subject");
+
+        block_on(visibility(&mut context, &item(entity))).unwrap()
+    }
+
+    #[test]
+    fn classifies_addressing() {
+        let followers = vec!["/actor/followers".to_owned()];
+        let cases: Vec<(Pairs, Visibility)> = vec![
+            (&[(as2!(to), &[as2!(Public)])], Visibility::Public),
+            (
+                &[
+                    (as2!(to), &["/actor/followers"]),
+                    (as2!(cc), &[as2!(Public)]),
+                ],
+                Visibility::Unlisted,
+            ),
+            (
+                &[(as2!(to), &["/actor/followers"])],
+                Visibility::FollowersOnly,
+            ),
+            (&[(as2!(to), &["/friend"])], Visibility::Direct),
+            (&[], Visibility::Direct),
+        ];
+
+        for (pairs, expected) in cases {
+            let entity = entity("/note", &[as2!(Note)], pairs);
+            assert_eq!(Visibility::of(&entity, &followers), expected, "{:?}", pairs);
+        }
+
+        assert!(!Visibility::of(&entity("/note", &[], &[]), &[]).is_public());
+    }
+
+    #[test]
+    fn looks_up_followers_and_collections() {
+        let actor = entity(
+            "/actor",
+            &[as2!(Person)],
+            &[(as2!(followers), &["/actor/followers"])],
+        );
+        let friends = entity("/friends", &[as2!(Collection)], &[]);
+        let friend = entity("/friend", &[as2!(Person)], &[]);
+        let store = || vec![actor.clone(), friends.clone(), friend.clone()];
+
+        let note = |to: &str| {
+            entity(
+                "/note",
+                &[as2!(Note)],
+                &[(as2!(attributedTo), &["/actor"]), (as2!(to), &[to])],
+            )
+        };
+
+        assert_eq!(
+            classify(note("/actor/followers"), store()),
+            Visibility::FollowersOnly
+        );
+        assert_eq!(classify(note("/friends"), store()), Visibility::Limited);
+        assert_eq!(classify(note("/friend"), store()), Visibility::Direct);
+        assert_eq!(
+            classify(entity("/note", &[as2!(Note)], &[]), store()),
+            Visibility::Direct
+        );
+    }
+}